@@ -0,0 +1,11 @@
+-- Add migration script here
+CREATE TABLE simulations(
+    id UUID PRIMARY KEY,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    configuration JSONB NOT NULL,
+    seed BIGINT NOT NULL,
+    snapshots JSONB NOT NULL,
+    result JSONB NOT NULL
+);
+
+CREATE INDEX simulations_created_at_idx ON simulations (created_at DESC);
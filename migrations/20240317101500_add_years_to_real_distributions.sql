@@ -0,0 +1,5 @@
+ALTER TABLE real_distributions ADD COLUMN years INTEGER [];
+-- The existing rows were seeded from the bundled CSV files, whose consecutive years end in 2022
+UPDATE real_distributions
+SET years = ARRAY(SELECT generate_series(2023 - COALESCE(cardinality(data), 0), 2022));
+ALTER TABLE real_distributions ALTER COLUMN years SET NOT NULL;
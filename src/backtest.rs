@@ -0,0 +1,225 @@
+use crate::distributions;
+use crate::error;
+use crate::investment_config;
+use crate::types;
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct RollingWindowResult {
+    start_year: i32,
+    final_balance: f64,
+    real_final_balance: f64,
+    depletion_year: Option<usize>,
+    success: bool,
+}
+
+#[derive(serde::Serialize, Debug)]
+pub struct RollingAnalysisResult {
+    windows: usize,
+    success_rate: f64,
+    best: RollingWindowResult,
+    worst: RollingWindowResult,
+    median: RollingWindowResult,
+    results: Vec<RollingWindowResult>,
+}
+
+/// Runs the configuration once for every start year in which all its historical distributions
+/// cover `years` consecutive years, replaying history in order instead of sampling it
+pub fn rolling_analysis(
+    config: &investment_config::Configuration,
+    seed: u64,
+    distributions: &dyn distributions::DistributionRepository,
+) -> Result<RollingAnalysisResult, error::SimulationError> {
+    let names = distribution_names(config);
+    if names.is_empty() {
+        return Err(error::SimulationError::MissingHistoricalDistribution);
+    }
+
+    let selected = names
+        .iter()
+        .map(|(name, series)| distributions.get_series(*series, name))
+        .collect::<Result<Vec<&distributions::Distribution>, error::SimulationError>>()?;
+    let start_years: Vec<i32> = selected[0]
+        .years
+        .iter()
+        .copied()
+        .filter(|&start_year| {
+            (start_year..start_year + config.years as i32).all(|year| {
+                selected
+                    .iter()
+                    .all(|distribution| distribution.years.contains(&year))
+            })
+        })
+        .collect();
+
+    let mut results: Vec<RollingWindowResult> = Vec::with_capacity(start_years.len());
+    for start_year in start_years {
+        let result = with_start_year(config, start_year)
+            .simulate(seed, distributions)?
+            .result;
+        results.push(RollingWindowResult {
+            start_year,
+            final_balance: result.final_balance(),
+            real_final_balance: result.real_final_balance(),
+            depletion_year: result.depletion_year(),
+            success: is_success(config, &result),
+        });
+    }
+    if results.is_empty() {
+        return Err(error::SimulationError::NotEnoughHistory(config.years));
+    }
+
+    let mut sorted = results.clone();
+    sorted.sort_by(|a, b| a.final_balance.total_cmp(&b.final_balance));
+    let successes = results.iter().filter(|result| result.success).count();
+
+    Ok(RollingAnalysisResult {
+        windows: results.len(),
+        success_rate: successes as f64 / results.len() as f64,
+        best: sorted[sorted.len() - 1].clone(),
+        worst: sorted[0].clone(),
+        median: sorted[sorted.len() / 2].clone(),
+        results,
+    })
+}
+
+/// A window succeeds when the money never runs out and the target balance, if any, is reached
+fn is_success(
+    config: &investment_config::Configuration,
+    result: &crate::investment::InvestmentResult,
+) -> bool {
+    let target_reached = match config.target_balance {
+        Some(target) => result.final_balance() >= target,
+        None => true,
+    };
+    result.depletion_year().is_none() && target_reached
+}
+
+fn distribution_names(
+    config: &investment_config::Configuration,
+) -> Vec<(String, distributions::Series)> {
+    let mut interests: Vec<(&types::Interest, distributions::Series)> = Vec::new();
+    interests.extend(
+        config
+            .return_rates
+            .iter()
+            .map(|interest| (interest, distributions::Series::Returns)),
+    );
+    if let Some(portfolio) = &config.portfolio {
+        interests.extend(
+            portfolio
+                .assets
+                .iter()
+                .map(|asset| (&asset.return_rates, distributions::Series::Returns)),
+        );
+    }
+    interests.extend(
+        config
+            .inflation
+            .iter()
+            .map(|interest| (interest, distributions::Series::Inflation)),
+    );
+
+    let mut names: Vec<(String, distributions::Series)> = Vec::new();
+    for (name, series) in interests
+        .iter()
+        .filter_map(|(interest, series)| Some((interest.distribution_name()?, *series)))
+    {
+        if !names
+            .iter()
+            .any(|(existing, existing_series)| existing == name && *existing_series == series)
+        {
+            names.push((name.to_string(), series));
+        }
+    }
+    names
+}
+
+/// Copy of the configuration replaying every historical distribution from `start_year`
+fn with_start_year(
+    config: &investment_config::Configuration,
+    start_year: i32,
+) -> investment_config::Configuration {
+    let historical = |interest: &types::Interest| match interest.distribution_name() {
+        Some(name) => types::Interest::Historical {
+            distribution: name.to_string(),
+            start_year,
+        },
+        None => interest.clone(),
+    };
+
+    let mut config = config.clone();
+    config.return_rates = config.return_rates.as_ref().map(historical);
+    config.inflation = config.inflation.as_ref().map(historical);
+    if let Some(portfolio) = config.portfolio.as_mut() {
+        for asset in portfolio.assets.iter_mut() {
+            asset.return_rates = historical(&asset.return_rates);
+        }
+    }
+    config
+}
+
+#[cfg(test)]
+mod test {
+    use super::rolling_analysis;
+    use crate::distributions::{CsvRepository, DEFAULT_DIRECTORY};
+    use crate::investment_config::Configuration;
+
+    #[test]
+    fn test_rolling_analysis_covers_every_window() {
+        let config: Configuration = serde_json::from_value(serde_json::json!({
+            "deposit": 10000,
+            "years": 10,
+            "return_rates": "sp500",
+            "annual_contributions": 1000.0,
+        }))
+        .unwrap();
+        let repository = CsvRepository::load(DEFAULT_DIRECTORY).unwrap();
+        let result = rolling_analysis(&config, 0, &repository).unwrap();
+
+        // sp500 covers 30 years, 1993 to 2022
+        assert_eq!(result.windows, 21);
+        assert_eq!(result.results[0].start_year, 1993);
+        assert_eq!(result.results[20].start_year, 2013);
+        assert!(result.worst.final_balance <= result.median.final_balance);
+        assert!(result.median.final_balance <= result.best.final_balance);
+        assert_eq!(result.success_rate, 1.0);
+    }
+
+    #[test]
+    fn test_rolling_analysis_with_target_balance() {
+        let config: Configuration = serde_json::from_value(serde_json::json!({
+            "deposit": 10000,
+            "years": 10,
+            "return_rates": "sp500",
+            "annual_contributions": 0.0,
+            "target_balance": 1000000.0,
+        }))
+        .unwrap();
+        let repository = CsvRepository::load(DEFAULT_DIRECTORY).unwrap();
+        let result = rolling_analysis(&config, 0, &repository).unwrap();
+
+        assert_eq!(result.success_rate, 0.0);
+    }
+
+    #[test]
+    fn test_rolling_analysis_needs_a_distribution() {
+        let repository = CsvRepository::load(DEFAULT_DIRECTORY).unwrap();
+        let config: Configuration = serde_json::from_value(serde_json::json!({
+            "deposit": 10000,
+            "years": 10,
+            "return_rates": 0.05,
+            "annual_contributions": 0.0,
+        }))
+        .unwrap();
+        assert!(rolling_analysis(&config, 0, &repository).is_err());
+
+        let config: Configuration = serde_json::from_value(serde_json::json!({
+            "deposit": 10000,
+            "years": 100,
+            "return_rates": "sp500",
+            "annual_contributions": 0.0,
+        }))
+        .unwrap();
+        assert!(rolling_analysis(&config, 0, &repository).is_err());
+    }
+}
@@ -1,39 +1,211 @@
-use crate::investment;
+use crate::backtest;
+use crate::compare;
+use crate::distributions;
+use crate::error;
 use crate::investment_config;
-use crate::types;
+use crate::monte_carlo;
+use crate::sensitivity;
+use crate::solver;
+use crate::statistics;
+use crate::sweep;
+use crate::validation;
 
-pub fn run_cli_simulation(config_file: String) {
-    let config: investment_config::Configuration = config::Config::builder()
-        .add_source(config::File::new(&config_file, config::FileFormat::Json))
+fn read_file<T: serde::de::DeserializeOwned>(config_file: &str) -> T {
+    config::Config::builder()
+        .add_source(config::File::new(config_file, config::FileFormat::Json))
         .build()
         .expect("Error loading configuration file")
         .try_deserialize()
-        .expect("Error deserializing the configuration");
-
-    let investment = investment::Investment::new(
-        types::PositiveFloat::try_from(config.deposit as f64).unwrap(),
-        config.years,
-        config
-            .annual_contributions
-            .to_annual_contributions(config.years),
-        config.return_rates.to_interest_rates(config.years),
-    );
+        .expect("Error deserializing the configuration")
+}
 
-    let investment_snapshots = investment.simulate().unwrap();
-    let investment_results: Vec<investment::InvestmentSnapshotResult> = investment_snapshots
-        .iter()
-        .map(|snapshot| snapshot.result())
-        .collect();
-    for (year, result) in investment_results.iter().enumerate() {
+fn exit_with_errors(config_file: &str, errors: Vec<error::FieldError>) -> ! {
+    eprintln!("Error: invalid configuration file `{}`", config_file);
+    for field_error in errors {
+        eprintln!(" - `{}` {}", field_error.field, field_error.reason);
+    }
+    std::process::exit(1);
+}
+
+fn load_configuration(
+    config_file: &str,
+    distributions: &dyn distributions::DistributionRepository,
+) -> investment_config::Configuration {
+    let config: investment_config::Configuration = read_file(config_file);
+
+    if let Err(error::SimulationError::Validation(errors)) =
+        validation::validate_configuration(&config, distributions)
+    {
+        exit_with_errors(config_file, errors);
+    }
+    config
+}
+
+pub fn run_cli_simulation(
+    config_file: String,
+    seed: Option<u64>,
+    distributions: &dyn distributions::DistributionRepository,
+) {
+    let config = load_configuration(&config_file, distributions);
+    let seed = seed.unwrap_or_else(|| config.seed());
+
+    let simulation = config.simulate(seed, distributions).unwrap();
+    for (year, result) in simulation.snapshots.iter().enumerate() {
         println!(
             "Investment result year {}\n {}",
             year + 1,
             serde_json::to_string(result).unwrap()
         );
     }
-    let investment_result = investment::get_investment_result(investment_results).unwrap();
     println!(
         "Investment result\n {}",
-        serde_json::to_string(&investment_result).unwrap()
+        serde_json::to_string(&simulation.result).unwrap()
+    );
+
+    if let Some(simulations) = config.simulations {
+        let monte_carlo_result =
+            monte_carlo::run_monte_carlo(&config, simulations, seed, distributions).unwrap();
+        println!(
+            "Monte Carlo result\n {}",
+            serde_json::to_string(&monte_carlo_result).unwrap()
+        );
+    }
+}
+
+pub fn run_cli_rolling_analysis(
+    config_file: String,
+    seed: Option<u64>,
+    distributions: &dyn distributions::DistributionRepository,
+) {
+    let config = load_configuration(&config_file, distributions);
+    let seed = seed.unwrap_or_else(|| config.seed());
+
+    let rolling_result = backtest::rolling_analysis(&config, seed, distributions).unwrap();
+    println!(
+        "Rolling analysis result\n {}",
+        serde_json::to_string(&rolling_result).unwrap()
     );
 }
+
+pub fn run_cli_solver(
+    config_file: String,
+    goal: solver::Goal,
+    seed: Option<u64>,
+    distributions: &dyn distributions::DistributionRepository,
+) {
+    let config = load_configuration(&config_file, distributions);
+    let seed = seed.unwrap_or_else(|| config.seed());
+
+    match solver::solve(&config, goal, seed, distributions) {
+        Ok(solve_result) => println!(
+            "Solver result\n {}",
+            serde_json::to_string(&solve_result).unwrap()
+        ),
+        Err(error) => {
+            eprintln!("Error: {}", error);
+            std::process::exit(1);
+        }
+    }
+}
+
+pub fn run_cli_sensitivity_analysis(
+    config_file: String,
+    variations: sensitivity::Variations,
+    seed: Option<u64>,
+    distributions: &dyn distributions::DistributionRepository,
+) {
+    let config = load_configuration(&config_file, distributions);
+    let seed = seed.unwrap_or_else(|| config.seed());
+
+    match sensitivity::sensitivity_analysis(&config, variations, seed, distributions) {
+        Ok(sensitivity_result) => print!("{}", sensitivity_result),
+        Err(error) => {
+            eprintln!("Error: {}", error);
+            std::process::exit(1);
+        }
+    }
+}
+
+pub fn run_cli_sweep(
+    config_file: String,
+    format: sweep::Format,
+    seed: Option<u64>,
+    distributions: &dyn distributions::DistributionRepository,
+) {
+    let request: sweep::SweepRequest = read_file(&config_file);
+    let seed = seed.unwrap_or_else(|| request.config.seed());
+
+    match sweep::run_sweep(&request.config, &request.sweep, seed, distributions) {
+        Ok(sweep_result) => match format {
+            sweep::Format::Json => println!("{}", serde_json::to_string(&sweep_result).unwrap()),
+            sweep::Format::Csv => print!("{}", sweep_result.to_csv().unwrap()),
+        },
+        Err(error::SimulationError::Validation(errors)) => exit_with_errors(&config_file, errors),
+        Err(error) => {
+            eprintln!("Error: {}", error);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Every scenario is named after the stem of its configuration file
+pub fn run_cli_comparison(
+    config_files: Vec<String>,
+    seed: Option<u64>,
+    distributions: &dyn distributions::DistributionRepository,
+) {
+    let scenarios: Vec<compare::Scenario> = config_files
+        .iter()
+        .map(|config_file| compare::Scenario {
+            name: std::path::Path::new(config_file).file_stem().map_or_else(
+                || config_file.clone(),
+                |stem| stem.to_string_lossy().into_owned(),
+            ),
+            config: load_configuration(config_file, distributions),
+        })
+        .collect();
+    let seed = seed.unwrap_or_else(|| scenarios[0].config.seed());
+
+    match compare::compare(&scenarios, seed, distributions) {
+        Ok(comparison_result) => println!(
+            "Comparison result\n {}",
+            serde_json::to_string(&comparison_result).unwrap()
+        ),
+        Err(error::SimulationError::Validation(errors)) => {
+            for field_error in errors {
+                eprintln!("Error: `{}` {}", field_error.field, field_error.reason);
+            }
+            std::process::exit(1);
+        }
+        Err(error) => {
+            eprintln!("Error: {}", error);
+            std::process::exit(1);
+        }
+    }
+}
+
+pub fn run_cli_distribution_statistics(
+    name: String,
+    bins: usize,
+    distributions: &dyn distributions::DistributionRepository,
+) {
+    let statistics = distributions
+        .get(&name)
+        .and_then(|distribution| statistics::distribution_statistics(&name, distribution, bins));
+    match statistics {
+        Ok(statistics) => println!(
+            "Distribution statistics\n {}",
+            serde_json::to_string(&statistics).unwrap()
+        ),
+        Err(error::SimulationError::Validation(errors)) => {
+            for field_error in errors {
+                eprintln!("Error: `{}` {}", field_error.field, field_error.reason);
+            }
+            std::process::exit(1);
+        }
+        Err(error) => {
+            eprintln!("Error: {}", error);
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,399 @@
+use crate::distributions;
+use crate::error;
+use crate::investment;
+use crate::investment_config;
+use crate::types;
+use crate::validation;
+
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+pub struct Scenario {
+    pub name: String,
+    #[serde(flatten)]
+    pub config: investment_config::Configuration,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+pub struct ComparisonRequest {
+    /// The seed of the first scenario is used when none is set
+    pub seed: Option<u64>,
+    pub scenarios: Vec<Scenario>,
+}
+
+#[derive(serde::Serialize, Debug)]
+pub struct ScenarioResult {
+    name: String,
+    result: investment::InvestmentResult,
+}
+
+#[derive(serde::Serialize, Debug, PartialEq)]
+pub struct YearDifference {
+    year: usize,
+    final_balance: f64,
+    fees_paid: f64,
+    net_contribution: f64,
+}
+
+#[derive(serde::Serialize, Debug, PartialEq)]
+pub struct MetricsDifference {
+    cagr: f64,
+    money_weighted_return: Option<f64>,
+    volatility: f64,
+    max_drawdown: Option<f64>,
+    sharpe_ratio: Option<f64>,
+    sortino_ratio: Option<f64>,
+}
+
+/// Scenario minus the baseline, the first scenario
+#[derive(serde::Serialize, Debug)]
+pub struct ScenarioDifference {
+    name: String,
+    final_balance: f64,
+    total_fees: f64,
+    net_contributions: f64,
+    metrics: MetricsDifference,
+    /// Only the years simulated by both scenarios
+    years: Vec<YearDifference>,
+}
+
+#[derive(serde::Serialize, Debug)]
+pub struct ComparisonResult {
+    seed: u64,
+    baseline: String,
+    scenarios: Vec<ScenarioResult>,
+    differences: Vec<ScenarioDifference>,
+}
+
+/// Simulates every scenario with the same seed and compares them with the first one. The random
+/// returns and inflation of every scenario must be sampled like the baseline's, over the same years
+/// and granularity, so all of them follow the same path.
+pub fn compare(
+    scenarios: &[Scenario],
+    seed: u64,
+    distributions: &dyn distributions::DistributionRepository,
+) -> Result<ComparisonResult, error::SimulationError> {
+    if scenarios.len() < 2 {
+        return Err(error::SimulationError::Validation(vec![
+            error::FieldError::new("scenarios", "needs at least two scenarios"),
+        ]));
+    }
+    let mut errors = Vec::new();
+    for (i, scenario) in scenarios.iter().enumerate() {
+        if let Err(error::SimulationError::Validation(scenario_errors)) =
+            validation::validate_configuration(&scenario.config, distributions)
+        {
+            errors.extend(scenario_errors.into_iter().map(|field_error| {
+                error::FieldError::new(
+                    &format!("scenarios[{}].{}", i, field_error.field),
+                    field_error.reason,
+                )
+            }));
+        }
+    }
+    if !errors.is_empty() {
+        return Err(error::SimulationError::Validation(errors));
+    }
+    let baseline = &scenarios[0].config;
+    let inflation = |config: &investment_config::Configuration| -> Vec<types::Interest> {
+        config.inflation.iter().cloned().collect()
+    };
+    let is_sampled = !random_sources(&return_sources(baseline)).is_empty()
+        || !random_sources(&inflation(baseline)).is_empty();
+    for (i, scenario) in scenarios.iter().enumerate().skip(1) {
+        let config = &scenario.config;
+        if differ(&return_sources(config), &return_sources(baseline)) {
+            let field = match config.portfolio {
+                Some(_) => "portfolio.assets",
+                None => "return_rates",
+            };
+            errors.push(error::FieldError::new(
+                &format!("scenarios[{}].{}", i, field),
+                "must sample the returns like the first scenario",
+            ));
+        }
+        if differ(&inflation(config), &inflation(baseline)) {
+            errors.push(error::FieldError::new(
+                &format!("scenarios[{}].inflation", i),
+                "must sample the inflation like the first scenario",
+            ));
+        }
+        // The sampled rates are drawn one after the other, so other lengths shift the path
+        if is_sampled && config.years != baseline.years {
+            errors.push(error::FieldError::new(
+                &format!("scenarios[{}].years", i),
+                "must match the first scenario when the returns or inflation are sampled",
+            ));
+        }
+        if is_sampled && config.granularity != baseline.granularity {
+            errors.push(error::FieldError::new(
+                &format!("scenarios[{}].granularity", i),
+                "must match the first scenario when the returns or inflation are sampled",
+            ));
+        }
+    }
+    if !errors.is_empty() {
+        return Err(error::SimulationError::Validation(errors));
+    }
+
+    let simulations = scenarios
+        .iter()
+        .map(|scenario| scenario.config.simulate(seed, distributions))
+        .collect::<Result<Vec<investment::Simulation>, error::SimulationError>>()?;
+    let baseline = &simulations[0];
+    let differences = scenarios
+        .iter()
+        .zip(simulations.iter())
+        .skip(1)
+        .map(|(scenario, simulation)| difference(&scenario.name, simulation, baseline))
+        .collect();
+
+    Ok(ComparisonResult {
+        seed,
+        baseline: scenarios[0].name.clone(),
+        scenarios: scenarios
+            .iter()
+            .zip(simulations)
+            .map(|(scenario, simulation)| ScenarioResult {
+                name: scenario.name.clone(),
+                result: simulation.result,
+            })
+            .collect(),
+        differences,
+    })
+}
+
+/// Rates of the returns, one per asset of the portfolio
+fn return_sources(config: &investment_config::Configuration) -> Vec<types::Interest> {
+    match &config.portfolio {
+        Some(portfolio) => portfolio
+            .assets
+            .iter()
+            .map(|asset| asset.return_rates.clone())
+            .collect(),
+        None => config.return_rates.iter().cloned().collect(),
+    }
+}
+
+/// Random rates only follow the same path when they are sampled in the same way, fixed rates may
+/// change freely
+fn differ(sources: &[types::Interest], baseline: &[types::Interest]) -> bool {
+    random_sources(sources) != random_sources(baseline)
+}
+
+fn random_sources(sources: &[types::Interest]) -> Vec<Option<serde_json::Value>> {
+    sources
+        .iter()
+        .filter(|interest| {
+            interest.sampling().is_some() || matches!(interest, types::Interest::Model(_))
+        })
+        .map(|interest| serde_json::to_value(interest).ok())
+        .collect()
+}
+
+fn difference(
+    name: &str,
+    simulation: &investment::Simulation,
+    baseline: &investment::Simulation,
+) -> ScenarioDifference {
+    let option_difference = |value: Option<f64>, baseline: Option<f64>| match (value, baseline) {
+        (Some(value), Some(baseline)) => Some(value - baseline),
+        _ => None,
+    };
+    let (result, base) = (&simulation.result, &baseline.result);
+    let (metrics, base_metrics) = (result.metrics(), base.metrics());
+
+    ScenarioDifference {
+        name: name.to_string(),
+        final_balance: result.final_balance() - base.final_balance(),
+        total_fees: result.total_fees() - base.total_fees(),
+        net_contributions: result.net_contributions() - base.net_contributions(),
+        metrics: MetricsDifference {
+            cagr: metrics.cagr() - base_metrics.cagr(),
+            money_weighted_return: option_difference(
+                metrics.money_weighted_return(),
+                base_metrics.money_weighted_return(),
+            ),
+            volatility: metrics.volatility() - base_metrics.volatility(),
+            max_drawdown: option_difference(metrics.max_drawdown(), base_metrics.max_drawdown()),
+            sharpe_ratio: option_difference(metrics.sharpe_ratio(), base_metrics.sharpe_ratio()),
+            sortino_ratio: option_difference(metrics.sortino_ratio(), base_metrics.sortino_ratio()),
+        },
+        years: simulation
+            .snapshots
+            .iter()
+            .zip(baseline.snapshots.iter())
+            .map(|(snapshot, base_snapshot)| YearDifference {
+                year: snapshot.year(),
+                final_balance: snapshot.final_balance() - base_snapshot.final_balance(),
+                fees_paid: snapshot.fees_paid() - base_snapshot.fees_paid(),
+                net_contribution: snapshot.net_contribution() - base_snapshot.net_contribution(),
+            })
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{compare, Scenario};
+    use crate::distributions::{CsvRepository, DEFAULT_DIRECTORY};
+    use assert_float_eq::assert_f64_near;
+
+    fn scenario(name: &str, config: serde_json::Value) -> Scenario {
+        Scenario {
+            name: name.to_string(),
+            config: serde_json::from_value(config).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_scenarios_share_the_return_path() {
+        let cheap = scenario(
+            "cheap",
+            serde_json::json!({
+                "deposit": 10000,
+                "years": 10,
+                "return_rates": "sp500",
+                "annual_contributions": 1000.0,
+            }),
+        );
+        let expensive = scenario(
+            "expensive",
+            serde_json::json!({
+                "deposit": 10000,
+                "years": 10,
+                "return_rates": "sp500",
+                "annual_contributions": 1000.0,
+                "fees": {"expense_ratio": 0.01},
+            }),
+        );
+        let repository = CsvRepository::load(DEFAULT_DIRECTORY).unwrap();
+        let result = compare(&[cheap, expensive], 42, &repository).unwrap();
+
+        let difference = &result.differences[0];
+        assert_eq!(difference.name, "expensive");
+        assert_eq!(difference.years.len(), 10);
+        assert_f64_near!(difference.net_contributions, 0.0);
+        assert!(difference.final_balance < 0.0);
+        assert!(difference.total_fees > 0.0);
+        // Both follow the same returns, so the fees are the only difference in the volatility
+        assert!(difference.metrics.volatility.abs() < 0.01);
+    }
+
+    #[test]
+    fn test_invalid_scenarios_are_reported() {
+        let repository = CsvRepository::load(DEFAULT_DIRECTORY).unwrap();
+        let valid = scenario(
+            "valid",
+            serde_json::json!({
+                "deposit": 10000,
+                "years": 3,
+                "return_rates": 0.05,
+                "annual_contributions": 0.0,
+            }),
+        );
+        assert!(compare(std::slice::from_ref(&valid), 0, &repository).is_err());
+
+        let invalid = scenario(
+            "invalid",
+            serde_json::json!({
+                "deposit": 10000,
+                "years": 0,
+                "return_rates": 0.05,
+                "annual_contributions": 0.0,
+            }),
+        );
+        match compare(&[valid, invalid], 0, &repository) {
+            Err(crate::error::SimulationError::Validation(errors)) => {
+                assert_eq!(errors[0].field, "scenarios[1].years")
+            }
+            _ => panic!("The invalid scenario must be reported"),
+        }
+    }
+
+    #[test]
+    fn test_scenarios_must_share_the_random_sources() {
+        let repository = CsvRepository::load(DEFAULT_DIRECTORY).unwrap();
+        let sp500 = scenario(
+            "sp500",
+            serde_json::json!({
+                "deposit": 10000,
+                "years": 10,
+                "return_rates": "sp500",
+                "annual_contributions": 0.0,
+                "inflation": "us_cpi",
+            }),
+        );
+        let msci_world = scenario(
+            "msci_world",
+            serde_json::json!({
+                "deposit": 10000,
+                "years": 10,
+                "return_rates": "msci_world",
+                "annual_contributions": 0.0,
+                "inflation": 0.02,
+            }),
+        );
+        match compare(&[sp500, msci_world], 0, &repository) {
+            Err(crate::error::SimulationError::Validation(errors)) => {
+                let fields: Vec<&str> = errors.iter().map(|error| error.field.as_str()).collect();
+                assert_eq!(
+                    fields,
+                    vec!["scenarios[1].return_rates", "scenarios[1].inflation"]
+                );
+            }
+            _ => panic!("The different return paths must be reported"),
+        }
+
+        // Fixed rates don't depend on the seed
+        let low = scenario(
+            "low",
+            serde_json::json!({
+                "deposit": 10000,
+                "years": 3,
+                "return_rates": 0.03,
+                "annual_contributions": 0.0,
+            }),
+        );
+        let high = scenario(
+            "high",
+            serde_json::json!({
+                "deposit": 10000,
+                "years": 3,
+                "return_rates": 0.07,
+                "annual_contributions": 0.0,
+            }),
+        );
+        assert!(compare(&[low, high], 0, &repository).is_ok());
+    }
+
+    #[test]
+    fn test_sampled_scenarios_must_share_the_years() {
+        let repository = CsvRepository::load(DEFAULT_DIRECTORY).unwrap();
+        let config = |years: usize, return_rates: serde_json::Value| {
+            serde_json::json!({
+                "deposit": 10000,
+                "years": years,
+                "return_rates": return_rates,
+                "annual_contributions": 0.0,
+            })
+        };
+        let short = scenario("short", config(10, serde_json::json!("sp500")));
+        let mut long = scenario("long", config(20, serde_json::json!("sp500")));
+        long.config.granularity = crate::types::Granularity::Monthly;
+        match compare(&[short, long], 0, &repository) {
+            Err(crate::error::SimulationError::Validation(errors)) => {
+                let fields: Vec<&str> = errors.iter().map(|error| error.field.as_str()).collect();
+                assert_eq!(
+                    fields,
+                    vec!["scenarios[1].years", "scenarios[1].granularity"]
+                );
+            }
+            _ => panic!("The different lengths must be reported"),
+        }
+
+        // Fixed rates follow the same path whatever the length
+        let short = scenario("short", config(10, serde_json::json!(0.05)));
+        let long = scenario("long", config(20, serde_json::json!(0.05)));
+        let result = compare(&[short, long], 0, &repository).unwrap();
+        assert_eq!(result.differences[0].years.len(), 10);
+    }
+}
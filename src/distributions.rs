@@ -1,58 +1,539 @@
+use crate::error;
 use csv::ReaderBuilder;
 use std::collections::HashMap;
+use std::path::Path;
 
-pub fn get_distributions() -> HashMap<&'static str, Vec<f64>> {
+/// Directory of the CSV files shipped with the application
+pub const DEFAULT_DIRECTORY: &str = "real_distributions";
+
+/// Name and file of every distribution, the CSV files store the yearly values in percent
+const DISTRIBUTION_FILES: [(&str, &str); 2] = [
+    ("sp500", "sp500_dist.csv"),
+    ("msci_world", "msci_world_dist.csv"),
+];
+/// Name and file of every inflation series, stored like the distributions
+const INFLATION_FILES: [(&str, &str); 1] = [("us_cpi", "us_cpi_dist.csv")];
+
+/// Namespace of the historical series. Inflation series are kept apart from the distributions
+/// of returns, so neither can be used as the other.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Series {
+    Returns,
+    Inflation,
+}
+
+/// Yearly values of a historical distribution, `years[i]` is the year of `values[i]`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Distribution {
+    pub years: Vec<i32>,
+    pub values: Vec<f64>,
+}
+
+impl Distribution {
+    pub fn value_for_year(&self, year: i32) -> Option<f64> {
+        self.years
+            .iter()
+            .position(|&distribution_year| distribution_year == year)
+            .map(|index| self.values[index])
+    }
+}
+
+/// Source of the historical distributions. The distributions are loaded once, when the
+/// repository is created, so lookups never touch the disk or the database.
+pub trait DistributionRepository: Send + Sync {
+    fn distributions(&self) -> &HashMap<String, Distribution>;
+
+    fn inflation_series(&self) -> &HashMap<String, Distribution>;
+
+    fn get(&self, name: &str) -> Result<&Distribution, error::SimulationError> {
+        self.distributions()
+            .get(name)
+            .ok_or_else(|| error::SimulationError::UnknownDistribution(name.to_string()))
+    }
+
+    fn get_series(
+        &self,
+        series: Series,
+        name: &str,
+    ) -> Result<&Distribution, error::SimulationError> {
+        match series {
+            Series::Returns => self.get(name),
+            Series::Inflation => self
+                .inflation_series()
+                .get(name)
+                .ok_or_else(|| error::SimulationError::UnknownInflationSeries(name.to_string())),
+        }
+    }
+
+    /// Names of every distribution, sorted
+    fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.distributions().keys().map(String::as_str).collect();
+        names.sort();
+        names
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryRepository {
+    distributions: HashMap<String, Distribution>,
+    inflation_series: HashMap<String, Distribution>,
+}
+
+impl InMemoryRepository {
+    pub fn new(distributions: HashMap<String, Distribution>) -> Self {
+        Self {
+            distributions,
+            inflation_series: HashMap::new(),
+        }
+    }
+
+    pub fn with_inflation_series(
+        mut self,
+        inflation_series: HashMap<String, Distribution>,
+    ) -> Self {
+        self.inflation_series = inflation_series;
+        self
+    }
+}
+
+impl DistributionRepository for InMemoryRepository {
+    fn distributions(&self) -> &HashMap<String, Distribution> {
+        &self.distributions
+    }
+
+    fn inflation_series(&self) -> &HashMap<String, Distribution> {
+        &self.inflation_series
+    }
+}
+
+/// Distributions and inflation series read from the CSV files of a directory
+#[derive(Debug, Clone)]
+pub struct CsvRepository {
+    distributions: HashMap<String, Distribution>,
+    inflation_series: HashMap<String, Distribution>,
+}
+
+impl CsvRepository {
+    pub fn load(directory: impl AsRef<Path>) -> Result<Self, error::ApplicationError> {
+        Ok(Self {
+            distributions: read_files(directory.as_ref(), &DISTRIBUTION_FILES)?,
+            inflation_series: read_files(directory.as_ref(), &INFLATION_FILES)?,
+        })
+    }
+}
+
+fn read_files(
+    directory: &Path,
+    files: &[(&str, &str)],
+) -> Result<HashMap<String, Distribution>, error::ApplicationError> {
     let mut distributions = HashMap::new();
-    println!(
-        "Working dir: {:?}",
-        std::env::current_dir().unwrap()
-    );
 
-    let mut sp500_reader = ReaderBuilder::new()
-        .from_path("real_distributions/sp500_dist.csv")
-        .expect("Error loading SP&500 distribution");
-    let mut msci_world_reader = ReaderBuilder::new()
-        .from_path("real_distributions/msci_world_dist.csv")
-        .expect("Error loading MSCI World distribution");
+    for &(name, file) in files {
+        let load_error = |reason: String| error::ApplicationError::DistributionLoadError {
+            name: name.to_string(),
+            reason,
+        };
+        let mut reader = ReaderBuilder::new()
+            .from_path(directory.join(file))
+            .map_err(|e| load_error(e.to_string()))?;
 
-    let mut sp500_dist: Vec<f64> = Vec::new();
-    let mut msci_world_dist: Vec<f64> = Vec::new();
+        let mut distribution = Distribution {
+            years: Vec::new(),
+            values: Vec::new(),
+        };
+        for record in reader.records() {
+            let record = record.map_err(|e| load_error(e.to_string()))?;
+            let year: i32 = record[0]
+                .parse()
+                .map_err(|_| load_error(format!("invalid year `{}`", &record[0])))?;
+            let rate: f64 = record[1]
+                .parse()
+                .map_err(|_| load_error(format!("invalid value `{}`", &record[1])))?;
+            distribution.years.push(year);
+            distribution.values.push(rate / 100.0);
+        }
 
-    for record in sp500_reader.records().flatten() {
-        let rate: f64 = record[1]
-            .parse()
-            .expect("Failure parsing a return interest into a f64");
-        sp500_dist.push(rate / 100.0);
+        distributions.insert(name.to_string(), distribution);
     }
 
-    if let Some(old_value) = distributions.insert("sp500", sp500_dist) {
-        panic!("Found an existing value for sp500: {:?}", old_value);
+    Ok(distributions)
+}
+
+impl DistributionRepository for CsvRepository {
+    fn distributions(&self) -> &HashMap<String, Distribution> {
+        &self.distributions
     }
 
-    for record in msci_world_reader.records().flatten() {
-        let rate: f64 = record[1]
-            .parse()
-            .expect("Failure parsing a return interest into a f64");
-        msci_world_dist.push(rate / 100.0);
+    fn inflation_series(&self) -> &HashMap<String, Distribution> {
+        &self.inflation_series
     }
+}
 
-    if let Some(old_value) = distributions.insert("msci_world", msci_world_dist) {
-        panic!("Found an existing value for msci_world: {:?}", old_value);
-    };
+/// Distributions stored in the `real_distributions` table. Writes go to the table first and
+/// then to the loaded distributions, keeping both in sync. The inflation series aren't managed
+/// through the table, rows named after one of them are ignored.
+#[derive(Debug, Clone)]
+pub struct PostgresRepository {
+    pool: sqlx::PgPool,
+    distributions: HashMap<String, Distribution>,
+    inflation_series: HashMap<String, Distribution>,
+}
+
+impl PostgresRepository {
+    pub async fn load(
+        pool: &sqlx::PgPool,
+        inflation_series: HashMap<String, Distribution>,
+    ) -> Result<Self, error::ApplicationError> {
+        let records = sqlx::query!("SELECT name, years, data FROM real_distributions")
+            .fetch_all(pool)
+            .await?;
+
+        let mut distributions = HashMap::new();
+        for record in records
+            .into_iter()
+            .filter(|record| !inflation_series.contains_key(&record.name))
+        {
+            let values = record.data.unwrap_or_default();
+            if values.len() != record.years.len() {
+                return Err(error::ApplicationError::DistributionLoadError {
+                    name: record.name,
+                    reason: format!("{} years but {} values", record.years.len(), values.len()),
+                });
+            }
+            distributions.insert(
+                record.name,
+                Distribution {
+                    years: record.years,
+                    values,
+                },
+            );
+        }
+
+        Ok(Self {
+            pool: pool.clone(),
+            distributions,
+            inflation_series,
+        })
+    }
+
+    pub async fn insert(
+        &mut self,
+        name: &str,
+        distribution: Distribution,
+    ) -> Result<(), error::ApplicationError> {
+        if self.inflation_series.contains_key(name) {
+            return Err(error::ApplicationError::DistributionAlreadyExists(
+                name.to_string(),
+            ));
+        }
+        let inserted = sqlx::query!(
+            "INSERT INTO real_distributions (name, years, data) VALUES ($1, $2, $3) ON CONFLICT (name) DO NOTHING",
+            name,
+            &distribution.years,
+            &distribution.values
+        )
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+        if inserted == 0 {
+            return Err(error::ApplicationError::DistributionAlreadyExists(
+                name.to_string(),
+            ));
+        }
+
+        self.distributions.insert(name.to_string(), distribution);
+        Ok(())
+    }
 
-    distributions
+    pub async fn update(
+        &mut self,
+        name: &str,
+        distribution: Distribution,
+    ) -> Result<(), error::ApplicationError> {
+        if self.inflation_series.contains_key(name) {
+            return Err(error::ApplicationError::DistributionNotFound(
+                name.to_string(),
+            ));
+        }
+        let updated = sqlx::query!(
+            "UPDATE real_distributions SET years = $2, data = $3 WHERE name = $1",
+            name,
+            &distribution.years,
+            &distribution.values
+        )
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+        if updated == 0 {
+            return Err(error::ApplicationError::DistributionNotFound(
+                name.to_string(),
+            ));
+        }
+
+        self.distributions.insert(name.to_string(), distribution);
+        Ok(())
+    }
+
+    pub async fn delete(&mut self, name: &str) -> Result<(), error::ApplicationError> {
+        if self.inflation_series.contains_key(name) {
+            return Err(error::ApplicationError::DistributionNotFound(
+                name.to_string(),
+            ));
+        }
+        let deleted = sqlx::query!("DELETE FROM real_distributions WHERE name = $1", name)
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+        if deleted == 0 {
+            return Err(error::ApplicationError::DistributionNotFound(
+                name.to_string(),
+            ));
+        }
+
+        self.distributions.remove(name);
+        Ok(())
+    }
+}
+
+impl DistributionRepository for PostgresRepository {
+    fn distributions(&self) -> &HashMap<String, Distribution> {
+        &self.distributions
+    }
+
+    fn inflation_series(&self) -> &HashMap<String, Distribution> {
+        &self.inflation_series
+    }
+}
+
+/// Unit of uploaded returns, there's no default so `7` can't be mistaken for 700%
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Unit {
+    Percent,
+    Fraction,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct YearlyReturn {
+    pub year: i32,
+    pub value: f64,
+}
+
+/// Yearly returns sent to create or replace a distribution
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct DistributionUpload {
+    pub unit: Unit,
+    pub returns: Vec<YearlyReturn>,
+}
+
+impl DistributionUpload {
+    /// Reads `year,value` rows, the first row being the header like in the bundled CSV files
+    pub fn from_csv(unit: Unit, csv: &str) -> Result<Self, error::ApplicationError> {
+        let mut returns = Vec::new();
+        for (row, record) in ReaderBuilder::new()
+            .from_reader(csv.as_bytes())
+            .records()
+            .enumerate()
+        {
+            let invalid_row = |reason: &str| {
+                error::ApplicationError::InvalidUpload(format!("row {}: {}", row + 1, reason))
+            };
+            let record = record.map_err(|e| invalid_row(&e.to_string()))?;
+            if record.len() != 2 {
+                return Err(invalid_row("expected a year and a value"));
+            }
+            returns.push(YearlyReturn {
+                year: record[0]
+                    .trim()
+                    .parse()
+                    .map_err(|_| invalid_row("invalid year"))?,
+                value: record[1]
+                    .trim()
+                    .parse()
+                    .map_err(|_| invalid_row("invalid value"))?,
+            });
+        }
+
+        Ok(Self { unit, returns })
+    }
+
+    /// Validates the returns and converts them to a distribution of fractions sorted by year
+    pub fn into_distribution(self) -> Result<Distribution, error::SimulationError> {
+        let mut errors = Vec::new();
+        if self.returns.is_empty() {
+            errors.push(error::FieldError::new(
+                "returns",
+                "needs at least one value",
+            ));
+        }
+        let scale = match self.unit {
+            Unit::Percent => 100.0,
+            Unit::Fraction => 1.0,
+        };
+        let mut returns: Vec<YearlyReturn> = Vec::with_capacity(self.returns.len());
+        for (i, yearly_return) in self.returns.iter().enumerate() {
+            let value = yearly_return.value / scale;
+            if !value.is_finite() || value <= -1.0 {
+                errors.push(error::FieldError::new(
+                    &format!("returns[{}].value", i),
+                    "must be a number greater than -100%",
+                ));
+            }
+            if returns
+                .iter()
+                .any(|existing| existing.year == yearly_return.year)
+            {
+                errors.push(error::FieldError::new(
+                    &format!("returns[{}].year", i),
+                    format!("the year {} is repeated", yearly_return.year),
+                ));
+            }
+            returns.push(YearlyReturn {
+                year: yearly_return.year,
+                value,
+            });
+        }
+        if !errors.is_empty() {
+            return Err(error::SimulationError::Validation(errors));
+        }
+
+        returns.sort_by_key(|yearly_return| yearly_return.year);
+        Ok(Distribution {
+            years: returns
+                .iter()
+                .map(|yearly_return| yearly_return.year)
+                .collect(),
+            values: returns
+                .iter()
+                .map(|yearly_return| yearly_return.value)
+                .collect(),
+        })
+    }
+}
+
+/// Names are used in URLs and configurations, so only letters, digits, `_` and `-` are allowed
+pub fn validate_name(name: &str) -> Result<(), error::SimulationError> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if valid {
+        Ok(())
+    } else {
+        Err(error::SimulationError::Validation(vec![
+            error::FieldError::new(
+                "name",
+                "must be a non-empty name made of letters, digits, `_` and `-`",
+            ),
+        ]))
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use super::get_distributions;
+    use super::{
+        validate_name, CsvRepository, DistributionRepository, DistributionUpload, Series, Unit,
+        YearlyReturn, DEFAULT_DIRECTORY,
+    };
+    use crate::error::SimulationError;
     use pretty_assertions::assert_eq;
 
     #[test]
     fn test_get_distributions() {
-        let distributions = get_distributions();
+        let repository = CsvRepository::load(DEFAULT_DIRECTORY).unwrap();
+
+        assert_eq!(repository.get("sp500").unwrap().values.len(), 30);
+        assert_eq!(repository.get("msci_world").unwrap().values.len(), 44);
+        assert_eq!(repository.names(), vec!["msci_world", "sp500"]);
+    }
+
+    #[test]
+    fn test_inflation_series_are_kept_apart() {
+        let repository = CsvRepository::load(DEFAULT_DIRECTORY).unwrap();
+
+        let us_cpi = repository.get_series(Series::Inflation, "us_cpi").unwrap();
+        assert_eq!(us_cpi.values.len(), 30);
+        assert!(repository.get("us_cpi").is_err());
+        assert!(matches!(
+            repository.get_series(Series::Inflation, "sp500"),
+            Err(SimulationError::UnknownInflationSeries(name)) if name == "sp500"
+        ));
+    }
+
+    #[test]
+    fn test_distributions_keep_their_years() {
+        let repository = CsvRepository::load(DEFAULT_DIRECTORY).unwrap();
+        let sp500 = repository.get("sp500").unwrap();
+
+        assert_eq!(sp500.years.len(), sp500.values.len());
+        assert_eq!(sp500.years[0], 1993);
+        assert_eq!(sp500.value_for_year(1993), Some(sp500.values[0]));
+        assert_eq!(sp500.value_for_year(1900), None);
+    }
+
+    #[test]
+    fn test_unknown_distribution() {
+        let repository = CsvRepository::load(DEFAULT_DIRECTORY).unwrap();
+
+        assert!(repository.get("non-existing-dist").is_err());
+        assert!(CsvRepository::load("non-existing-dir").is_err());
+    }
+
+    #[test]
+    fn test_upload_units_and_order() {
+        let percent =
+            DistributionUpload::from_csv(Unit::Percent, "Year,Return\n2001,-5.0\n2000,10\n")
+                .unwrap()
+                .into_distribution()
+                .unwrap();
+        assert_eq!(percent.years, vec![2000, 2001]);
+        assert_eq!(percent.values, vec![0.1, -0.05]);
+
+        let fraction = DistributionUpload {
+            unit: Unit::Fraction,
+            returns: vec![YearlyReturn {
+                year: 2000,
+                value: 0.1,
+            }],
+        }
+        .into_distribution()
+        .unwrap();
+        assert_eq!(fraction.values, vec![0.1]);
+    }
+
+    #[test]
+    fn test_invalid_uploads() {
+        let upload: DistributionUpload = serde_json::from_value(serde_json::json!({
+            "unit": "percent",
+            "returns": [
+                {"year": 2000, "value": 5.0},
+                {"year": 2000, "value": 7.0},
+                {"year": 2001, "value": -120.0},
+            ],
+        }))
+        .unwrap();
+        match upload.into_distribution() {
+            Err(SimulationError::Validation(errors)) => {
+                let fields: Vec<String> = errors.into_iter().map(|error| error.field).collect();
+                assert_eq!(fields, vec!["returns[1].year", "returns[2].value"]);
+            }
+            result => panic!("Unexpected result {:?}", result),
+        }
 
-        assert_eq!(distributions.get("sp500").unwrap().len(), 30);
-        assert_eq!(distributions.get("msci_world").unwrap().len(), 44);
+        assert!(
+            DistributionUpload::from_csv(Unit::Fraction, "Year,Return\n2000,NaN\n")
+                .unwrap()
+                .into_distribution()
+                .is_err()
+        );
+        assert!(DistributionUpload::from_csv(Unit::Fraction, "Year,Return\n2000,abc\n").is_err());
+        assert!(
+            serde_json::from_value::<DistributionUpload>(serde_json::json!({
+                "returns": [{"year": 2000, "value": 5.0}],
+            }))
+            .is_err()
+        );
+        assert!(validate_name("bonds_eu-2").is_ok());
+        assert!(validate_name("bonds eu").is_err());
     }
 }
@@ -1,3 +1,5 @@
+use axum::http::StatusCode;
+use axum::response;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -5,7 +7,54 @@ pub enum ApplicationError {
     #[error(transparent)]
     ConfigError(#[from] envy::Error),
     #[error(transparent)]
-    DatabaseError(#[from] sqlx::Error)
+    DatabaseError(#[from] sqlx::Error),
+    #[error(transparent)]
+    SerializationError(#[from] serde_json::Error),
+    #[error(transparent)]
+    SimulationError(#[from] SimulationError),
+    #[error("The simulation `{0}` doesn't exist")]
+    SimulationNotFound(uuid::Uuid),
+    #[error("Error loading the `{name}` distribution: {reason}")]
+    DistributionLoadError { name: String, reason: String },
+    #[error("The distribution `{0}` doesn't exist")]
+    DistributionNotFound(String),
+    #[error("The distribution `{0}` already exists")]
+    DistributionAlreadyExists(String),
+    #[error("Invalid upload: {0}")]
+    InvalidUpload(String),
+}
+
+impl response::IntoResponse for ApplicationError {
+    fn into_response(self) -> response::Response {
+        match self {
+            ApplicationError::SimulationError(error) => error.into_response(),
+            ApplicationError::SimulationNotFound(_) | ApplicationError::DistributionNotFound(_) => {
+                (
+                    StatusCode::NOT_FOUND,
+                    response::Json(serde_json::json!({ "error": self.to_string() })),
+                )
+                    .into_response()
+            }
+            ApplicationError::DistributionAlreadyExists(_) => (
+                StatusCode::CONFLICT,
+                response::Json(serde_json::json!({ "error": self.to_string() })),
+            )
+                .into_response(),
+            ApplicationError::InvalidUpload(_) => (
+                StatusCode::BAD_REQUEST,
+                response::Json(serde_json::json!({ "error": self.to_string() })),
+            )
+                .into_response(),
+            error => {
+                eprintln!("Internal error: {}", error);
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    response::Json(serde_json::json!({ "error": "Internal server error" })),
+                )
+                    .into_response()
+            }
+        }
+    }
 }
 
 #[derive(Error, Debug)]
@@ -14,6 +63,71 @@ pub enum SimulationError {
     TypeError(#[from] TypeError),
     #[error("Computed results are invalid")]
     InvalidInvestmentResults,
+    #[error("Either `return_rates` or `portfolio` must be configured")]
+    MissingReturnRates,
+    #[error("A portfolio needs assets with weights adding up to a positive value")]
+    InvalidPortfolio,
+    #[error("The rolling analysis needs rates following a historical distribution")]
+    MissingHistoricalDistribution,
+    #[error("The selected distributions don't share any year")]
+    NoSharedYears,
+    #[error("The historical distributions don't cover {0} consecutive years")]
+    NotEnoughHistory(usize),
+    #[error("Invalid parameters for the return model {0:?}")]
+    InvalidReturnModel(crate::return_models::ReturnModel),
+    #[error("At least two returns are needed to fit a model, found {0}")]
+    NotEnoughData(usize),
+    #[error("The distribution `{0}` doesn't exist")]
+    UnknownDistribution(String),
+    #[error("The inflation series `{0}` doesn't exist")]
+    UnknownInflationSeries(String),
+    #[error("Returns must be greater than -100%")]
+    InvalidReturns,
+    #[error("`{0}` has fewer values than the {1} simulated years")]
+    NotEnoughValues(&'static str, usize),
+    #[error("`target_balance` must be set to solve for a goal")]
+    MissingTargetBalance,
+    #[error("The target balance can't be reached by solving for {0:?}")]
+    UnreachableTarget(crate::solver::Unknown),
+    #[error("Invalid configuration")]
+    Validation(Vec<FieldError>),
+}
+
+impl response::IntoResponse for SimulationError {
+    fn into_response(self) -> response::Response {
+        match self {
+            SimulationError::Validation(errors) => (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                response::Json(serde_json::json!({ "errors": errors })),
+            )
+                .into_response(),
+            SimulationError::InvalidInvestmentResults => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                response::Json(serde_json::json!({ "error": self.to_string() })),
+            )
+                .into_response(),
+            error => (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                response::Json(serde_json::json!({ "error": error.to_string() })),
+            )
+                .into_response(),
+        }
+    }
+}
+
+#[derive(serde::Serialize, Debug, Clone, PartialEq)]
+pub struct FieldError {
+    pub field: String,
+    pub reason: String,
+}
+
+impl FieldError {
+    pub fn new(field: &str, reason: impl Into<String>) -> Self {
+        FieldError {
+            field: field.to_string(),
+            reason: reason.into(),
+        }
+    }
 }
 
 #[derive(Error, Debug)]
@@ -23,3 +137,30 @@ pub enum TypeError {
     #[error("NaN is invalid")]
     NaNInvalid,
 }
+
+#[cfg(test)]
+mod test {
+    use super::{ApplicationError, FieldError, SimulationError};
+    use axum::http::StatusCode;
+    use axum::response::IntoResponse;
+
+    #[test]
+    fn test_simulation_error_responses() {
+        let response =
+            SimulationError::Validation(vec![FieldError::new("years", "must be greater than 0")])
+                .into_response();
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let response = SimulationError::InvalidInvestmentResults.into_response();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[test]
+    fn test_application_error_responses() {
+        let response = ApplicationError::SimulationNotFound(uuid::Uuid::nil()).into_response();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let response = ApplicationError::from(SimulationError::MissingReturnRates).into_response();
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+}
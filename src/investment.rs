@@ -1,13 +1,126 @@
 use crate::error;
-use crate::types::PositiveFloat;
+use crate::metrics;
+use crate::types::{
+    periodic_rate, AccountType, ContributionTiming, Fees, Granularity, PositiveFloat, Rebalancing,
+    Taxes, Withdrawal,
+};
 use fake::Dummy;
 
+#[derive(Debug, Clone, Dummy)]
+pub struct PortfolioAsset {
+    name: String,
+    target_weight: f64,
+    return_rates: Vec<f64>,
+}
+
+impl PortfolioAsset {
+    pub fn new(name: String, target_weight: f64, return_rates: Vec<f64>) -> Self {
+        PortfolioAsset {
+            name,
+            target_weight,
+            return_rates,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Dummy)]
+pub struct Portfolio {
+    assets: Vec<PortfolioAsset>,
+    rebalancing: Rebalancing,
+}
+
+impl Portfolio {
+    /// The target weights are normalized so they always add up to one
+    pub fn new(
+        mut assets: Vec<PortfolioAsset>,
+        rebalancing: Rebalancing,
+    ) -> Result<Self, error::SimulationError> {
+        let total_weight: f64 = assets.iter().map(|asset| asset.target_weight).sum();
+        if assets.is_empty()
+            || total_weight.is_nan()
+            || total_weight <= 0.0
+            || assets.iter().any(|asset| asset.target_weight < 0.0)
+        {
+            return Err(error::SimulationError::InvalidPortfolio);
+        }
+        for asset in assets.iter_mut() {
+            asset.target_weight /= total_weight;
+        }
+
+        Ok(Portfolio {
+            assets,
+            rebalancing,
+        })
+    }
+
+    fn target_weights(&self) -> Vec<f64> {
+        self.assets
+            .iter()
+            .map(|asset| asset.target_weight)
+            .collect()
+    }
+
+    /// Weights at the start of the year, once the contribution is invested following the target
+    /// weights and the portfolio is rebalanced if needed
+    fn start_weights(&self, weights: &[f64], balance: f64, contribution: f64) -> Vec<f64> {
+        let total = balance + contribution;
+        if total <= 0.0 {
+            return self.target_weights();
+        }
+        let weights: Vec<f64> = weights
+            .iter()
+            .zip(self.assets.iter())
+            .map(|(weight, asset)| (weight * balance + asset.target_weight * contribution) / total)
+            .collect();
+
+        let rebalance = match self.rebalancing {
+            Rebalancing::Never => false,
+            Rebalancing::Annually => true,
+            Rebalancing::Threshold(threshold) => weights
+                .iter()
+                .zip(self.assets.iter())
+                .any(|(weight, asset)| (weight - asset.target_weight).abs() > threshold),
+        };
+        if rebalance {
+            self.target_weights()
+        } else {
+            weights
+        }
+    }
+
+    /// Return of the whole portfolio during the year and the weights after applying it
+    fn apply_returns(&self, year: usize, weights: &[f64]) -> (f64, Vec<f64>) {
+        let grown: Vec<f64> = weights
+            .iter()
+            .zip(self.assets.iter())
+            .map(|(weight, asset)| weight * (1.0 + asset.return_rates[year]))
+            .collect();
+        let total: f64 = grown.iter().sum();
+        if total <= 0.0 {
+            return (total - 1.0, self.target_weights());
+        }
+
+        (
+            total - 1.0,
+            grown.iter().map(|value| value / total).collect(),
+        )
+    }
+}
+
 #[derive(Debug, Clone, Dummy)]
 pub struct Investment {
     initial_deposit: PositiveFloat,
     investment_years: usize,
     annual_net_contributions: Vec<PositiveFloat>,
     return_rates: Vec<f64>,
+    withdrawal: Option<Withdrawal>,
+    inflation_rates: Vec<f64>,
+    index_contributions: bool,
+    fees: Fees,
+    taxes: Taxes,
+    portfolio: Option<Portfolio>,
+    granularity: Granularity,
+    contribution_timing: ContributionTiming,
 }
 
 impl Investment {
@@ -22,37 +135,278 @@ impl Investment {
             investment_years,
             annual_net_contributions,
             return_rates,
+            withdrawal: None,
+            inflation_rates: Vec::new(),
+            index_contributions: false,
+            fees: Fees::default(),
+            taxes: Taxes::default(),
+            portfolio: None,
+            granularity: Granularity::Annual,
+            contribution_timing: ContributionTiming::Start,
         }
     }
 
+    /// Moment of every period in which its contribution is invested. The initial deposit is
+    /// always invested at the start.
+    pub fn with_contribution_timing(mut self, contribution_timing: ContributionTiming) -> Self {
+        self.contribution_timing = contribution_timing;
+        self
+    }
+
+    /// With a monthly or weekly granularity, `return_rates` may hold a rate per period instead
+    /// of a rate per year
+    pub fn with_granularity(mut self, granularity: Granularity) -> Self {
+        self.granularity = granularity;
+        self
+    }
+
+    /// When a portfolio is set, its assets returns are used instead of `return_rates`
+    pub fn with_portfolio(mut self, portfolio: Option<Portfolio>) -> Self {
+        self.portfolio = portfolio;
+        self
+    }
+
+    pub fn with_fees(mut self, fees: Fees) -> Self {
+        self.fees = fees;
+        self
+    }
+
+    /// Withdrawals are gross amounts, the taxes due are reported apart and never paid from the
+    /// balance
+    pub fn with_taxes(mut self, taxes: Taxes) -> Self {
+        self.taxes = taxes;
+        self
+    }
+
+    /// Years without an inflation rate are considered to have no inflation. When
+    /// `index_contributions` is set, contributions grow with the accumulated inflation.
+    pub fn with_inflation(mut self, inflation_rates: Vec<f64>, index_contributions: bool) -> Self {
+        self.inflation_rates = inflation_rates;
+        self.index_contributions = index_contributions;
+        self
+    }
+
+    pub fn with_withdrawal(mut self, withdrawal: Option<Withdrawal>) -> Self {
+        self.withdrawal = withdrawal;
+        self
+    }
+
+    /// Adds `shift` to the return rate of every year, and to the returns of every asset of the
+    /// portfolio. Periodic returns are shifted by the share of every period.
+    pub fn with_shifted_returns(mut self, shift: f64) -> Self {
+        let periods = self.granularity.periods_per_year();
+        let return_shift = if self.return_rates.len() >= self.investment_years * periods {
+            shift / periods as f64
+        } else {
+            shift
+        };
+        for rate in self.return_rates.iter_mut() {
+            *rate += return_shift;
+        }
+        if let Some(portfolio) = self.portfolio.as_mut() {
+            for rate in portfolio
+                .assets
+                .iter_mut()
+                .flat_map(|asset| asset.return_rates.iter_mut())
+            {
+                *rate += shift;
+            }
+        }
+        self
+    }
+
+    /// Simulates every period, use `annual_results` to aggregate the periods of each year
     pub fn simulate(&self) -> Result<Vec<InvestmentSnapshot>, error::SimulationError> {
+        if self.annual_net_contributions.len() < self.investment_years {
+            return Err(error::SimulationError::NotEnoughValues(
+                "annual_contributions",
+                self.investment_years,
+            ));
+        }
+        let rates_available = match &self.portfolio {
+            Some(portfolio) => portfolio
+                .assets
+                .iter()
+                .all(|asset| asset.return_rates.len() >= self.investment_years),
+            None => self.return_rates.len() >= self.investment_years,
+        };
+        if !rates_available {
+            return Err(error::SimulationError::NotEnoughValues(
+                "return_rates",
+                self.investment_years,
+            ));
+        }
+        let periods = self.granularity.periods_per_year();
+        let periodic_returns = self.return_rates.len() >= self.investment_years * periods;
+        // Charging this rate every period charges the annual fee rate over the year
+        let periodic_fee_rate = -periodic_rate(-self.fees.annual_fee_rate(), periods);
+
         let mut simulation_results: Vec<InvestmentSnapshot> = Vec::new();
+        let mut previous_withdrawal: Option<f64> = None;
+        let mut previous_return_rate: Option<f64> = None;
+        // Money put in before the current year
+        let mut previous_contributions = 0.0;
+        // Price level at the start of the period relative to the start of the investment
+        let mut price_level = 1.0;
+        // Money invested and not withdrawn yet, including the reinvested dividends
+        let mut cost_basis = 0.0;
+        let mut weights: Vec<f64> = self
+            .portfolio
+            .as_ref()
+            .map_or_else(Vec::new, |portfolio| portfolio.target_weights());
 
-        for (i, year) in (0..self.investment_years).enumerate() {
-            let net_contribution: PositiveFloat = {
-                if year == 0 {
-                    self.initial_deposit.0 + self.annual_net_contributions[i].0
-                } else {
-                    simulation_results[i - 1].net_contribution.0
-                        + self.annual_net_contributions[i].0
+        for year in 0..self.investment_years {
+            let contribution = if self.index_contributions {
+                self.annual_net_contributions[year].0 * price_level
+            } else {
+                self.annual_net_contributions[year].0
+            };
+            let previous_balance = simulation_results
+                .last()
+                .map_or(0.0, |snapshot| snapshot.final_balance());
+
+            let annual_return_rate = match &self.portfolio {
+                Some(portfolio) => {
+                    // The weights only change once a year, when the contributions of the year
+                    // are invested following the target weights
+                    let deposit = if year == 0 {
+                        self.initial_deposit.0
+                    } else {
+                        0.0
+                    };
+                    let brokerage_fees = self.fees.brokerage_fee(deposit)
+                        + periods as f64 * self.fees.brokerage_fee(contribution / periods as f64);
+                    let start_weights = portfolio.start_weights(
+                        &weights,
+                        previous_balance,
+                        deposit + contribution - brokerage_fees,
+                    );
+                    let (return_rate, end_weights) = portfolio.apply_returns(year, &start_weights);
+                    weights = end_weights;
+                    return_rate
                 }
-            }
-            .try_into()?;
-            let initial_balance = {
-                if year == 0 {
-                    self.initial_deposit.0 + self.annual_net_contributions[i].0
+                None => self.return_rates[year],
+            };
+            let inflation_rate = periodic_rate(
+                self.inflation_rates.get(year).copied().unwrap_or(0.0),
+                periods,
+            );
+
+            let mut year_returns = 1.0;
+            for period in 0..periods {
+                let deposit = if year == 0 && period == 0 {
+                    self.initial_deposit.0
                 } else {
-                    simulation_results[i - 1].final_balance() + self.annual_net_contributions[i].0
+                    0.0
+                };
+                let period_contribution = contribution / periods as f64;
+                // Computed from the whole year's contribution to avoid accumulating rounding
+                let year_contributions = if period + 1 == periods {
+                    contribution
+                } else {
+                    contribution * (period + 1) as f64 / periods as f64
+                };
+                let net_contribution = previous_contributions
+                    + if year == 0 {
+                        self.initial_deposit.0
+                    } else {
+                        0.0
+                    }
+                    + year_contributions;
+                let brokerage_fees = if deposit > 0.0 {
+                    self.fees.brokerage_fee(deposit) + self.fees.brokerage_fee(period_contribution)
+                } else {
+                    self.fees.brokerage_fee(period_contribution)
+                };
+                let previous_balance = simulation_results
+                    .last()
+                    .map_or(0.0, |snapshot| snapshot.final_balance());
+                let period_start = year as f64 + period as f64 / periods as f64;
+                // Contributions invested later in the period are kept apart from the balance
+                // that earns the whole period's return
+                let (initial_balance, late_contribution, contribution_time) =
+                    match self.contribution_timing {
+                        ContributionTiming::Start => (
+                            previous_balance + deposit + period_contribution - brokerage_fees,
+                            0.0,
+                            period_start,
+                        ),
+                        timing => (
+                            previous_balance + deposit - self.fees.brokerage_fee(deposit),
+                            period_contribution - self.fees.brokerage_fee(period_contribution),
+                            period_start
+                                + if timing == ContributionTiming::Middle {
+                                    0.5 / periods as f64
+                                } else {
+                                    1.0 / periods as f64
+                                },
+                        ),
+                    };
+
+                // Withdrawals are taken at the start of the year, before the returns are applied
+                let mut withdrawal = 0.0;
+                let mut depleted = false;
+                if let Some(strategy) = self
+                    .withdrawal
+                    .as_ref()
+                    .filter(|w| period == 0 && year >= w.start_year)
+                {
+                    let requested = strategy.withdrawal_amount(
+                        initial_balance,
+                        previous_withdrawal,
+                        previous_return_rate,
+                    );
+                    previous_withdrawal = Some(requested);
+                    withdrawal = requested.min(initial_balance.max(0.0));
+                    depleted = requested >= initial_balance;
                 }
-            };
+                cost_basis += initial_balance - previous_balance;
+                let withdrawal_tax =
+                    self.taxes
+                        .withdrawal_tax(withdrawal, initial_balance, cost_basis);
+                if initial_balance > 0.0 {
+                    cost_basis -= cost_basis * withdrawal / initial_balance;
+                }
+                cost_basis += late_contribution;
+
+                let return_rate = if periodic_returns && self.portfolio.is_none() {
+                    self.return_rates[year * periods + period]
+                } else {
+                    periodic_rate(annual_return_rate, periods)
+                };
+                year_returns *= 1.0 + return_rate;
 
-            let investment_snapshot = InvestmentSnapshot::new(
-                year,
-                net_contribution,
-                initial_balance,
-                self.return_rates[i],
-            )?;
-            simulation_results.push(investment_snapshot);
+                let investment_snapshot = InvestmentSnapshot::new(
+                    year,
+                    net_contribution.try_into()?,
+                    initial_balance - withdrawal,
+                    return_rate,
+                )?
+                .with_late_contribution(late_contribution, self.contribution_timing)
+                .with_cash_flows(vec![
+                    (period_start, withdrawal - deposit),
+                    (contribution_time, -period_contribution),
+                ])
+                .with_withdrawal(withdrawal, depleted)
+                .with_fees(brokerage_fees, periodic_fee_rate)
+                .with_taxes(self.taxes, periods, cost_basis, withdrawal_tax)
+                .with_inflation(inflation_rate, price_level)?
+                .with_holdings(self.portfolio.as_ref().map_or_else(Vec::new, |portfolio| {
+                    portfolio
+                        .assets
+                        .iter()
+                        .zip(weights.iter())
+                        .map(|(asset, weight)| (asset.name.clone(), *weight))
+                        .collect()
+                }));
+                price_level = investment_snapshot.end_price_level();
+                cost_basis = investment_snapshot.end_cost_basis();
+                simulation_results.push(investment_snapshot);
+            }
+            previous_return_rate = Some(year_returns - 1.0);
+            previous_contributions = simulation_results
+                .last()
+                .map_or(0.0, |snapshot| snapshot.net_contribution.0);
         }
 
         Ok(simulation_results)
@@ -64,11 +418,65 @@ pub struct InvestmentResult {
     investment_years: usize,
     net_contributions: PositiveFloat,
     final_balance: f64,
+    real_final_balance: f64,
+    /// Final balance once withdrawn and taxed
+    after_tax_final_balance: f64,
     average_return_rate: f64,
+    total_fees: f64,
+    total_withdrawals: f64,
+    total_taxes: f64,
+    /// Final balance plus withdrawals minus the money put in
+    total_gains: f64,
+    depletion_year: Option<usize>,
+    metrics: metrics::PerformanceMetrics,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+}
+
+impl InvestmentResult {
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn final_balance(&self) -> f64 {
+        self.final_balance
+    }
+
+    pub fn real_final_balance(&self) -> f64 {
+        self.real_final_balance
+    }
+
+    pub fn after_tax_final_balance(&self) -> f64 {
+        self.after_tax_final_balance
+    }
+
+    pub fn net_contributions(&self) -> f64 {
+        self.net_contributions.0
+    }
+
+    pub fn total_fees(&self) -> f64 {
+        self.total_fees
+    }
+
+    pub fn depletion_year(&self) -> Option<usize> {
+        self.depletion_year
+    }
+
+    pub fn metrics(&self) -> &metrics::PerformanceMetrics {
+        &self.metrics
+    }
+}
+
+/// Per-year snapshots of a single simulated path together with its aggregated result
+pub struct Simulation {
+    pub snapshots: Vec<InvestmentSnapshotResult>,
+    pub result: InvestmentResult,
 }
 
 pub fn get_investment_result(
-    investment_information: Vec<InvestmentSnapshotResult>,
+    investment_information: &[InvestmentSnapshotResult],
+    risk_free_rate: f64,
 ) -> Result<InvestmentResult, error::SimulationError> {
     let last_year_result = match investment_information.last() {
         Some(result) => result,
@@ -80,23 +488,87 @@ pub fn get_investment_result(
         .map(|snapshot| snapshot.return_rate)
         .sum();
     let average_return_rate = sum / investment_information.len() as f64;
+    let total_fees: f64 = investment_information
+        .iter()
+        .map(|snapshot| snapshot.fees_paid)
+        .sum();
+    let total_withdrawals: f64 = investment_information
+        .iter()
+        .map(|snapshot| snapshot.withdrawal)
+        .sum();
+    let total_taxes: f64 = investment_information
+        .iter()
+        .map(|snapshot| snapshot.taxes_paid)
+        .sum();
+    let depletion_year = investment_information
+        .iter()
+        .find(|snapshot| snapshot.depleted)
+        .map(|snapshot| snapshot.year);
+
+    let returns: Vec<f64> = investment_information
+        .iter()
+        .map(|snapshot| snapshot.net_return)
+        .collect();
+    let cash_flows: Vec<(f64, f64)> = investment_information
+        .iter()
+        .flat_map(|snapshot| snapshot.cash_flows.iter().copied())
+        .collect();
+
     let investment_result = InvestmentResult {
         investment_years: investment_information.len(),
         net_contributions: last_year_result.net_contribution,
         final_balance: last_year_result.final_balance,
+        real_final_balance: last_year_result.real_final_balance,
+        after_tax_final_balance: last_year_result.after_tax_final_balance,
         average_return_rate,
+        total_fees,
+        total_withdrawals,
+        total_taxes,
+        total_gains: last_year_result.final_balance + total_withdrawals
+            - last_year_result.net_contribution.0,
+        depletion_year,
+        metrics: metrics::PerformanceMetrics::new(
+            &returns,
+            &cash_flows,
+            (
+                investment_information.len() as f64,
+                last_year_result.final_balance,
+            ),
+            risk_free_rate,
+        ),
+        seed: None,
     };
 
     Ok(investment_result)
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 #[cfg_attr(test, derive(fake::Dummy))]
 pub struct InvestmentSnapshot {
     year: usize,
     net_contribution: PositiveFloat,
     initial_balance: f64,
     return_rate: f64,
+    withdrawal: f64,
+    depleted: bool,
+    inflation_rate: f64,
+    price_level: f64,
+    brokerage_fees: f64,
+    annual_fee_rate: f64,
+    /// Name and weight of every asset of the portfolio at the end of the year
+    holdings: Vec<(String, f64)>,
+    /// Contribution, net of brokerage fees, invested after the start of the period
+    late_contribution: f64,
+    contribution_timing: ContributionTiming,
+    /// Time in years since the start and amount of the money taken (positive) or added
+    /// (negative) during the period
+    cash_flows: Vec<(f64, f64)>,
+    taxes: Taxes,
+    /// Share of the balance paid as taxable dividends during the period
+    dividend_yield: f64,
+    /// Cost basis once the withdrawal is taken and the contributions are invested
+    cost_basis: f64,
+    withdrawal_tax: f64,
 }
 
 impl InvestmentSnapshot {
@@ -114,21 +586,214 @@ impl InvestmentSnapshot {
             net_contribution,
             initial_balance,
             return_rate,
+            withdrawal: 0.0,
+            depleted: false,
+            inflation_rate: 0.0,
+            price_level: 1.0,
+            brokerage_fees: 0.0,
+            annual_fee_rate: 0.0,
+            holdings: Vec::new(),
+            late_contribution: 0.0,
+            contribution_timing: ContributionTiming::Start,
+            cash_flows: Vec::new(),
+            taxes: Taxes::default(),
+            dividend_yield: 0.0,
+            cost_basis: 0.0,
+            withdrawal_tax: 0.0,
         })
     }
 
+    fn with_late_contribution(
+        mut self,
+        late_contribution: f64,
+        contribution_timing: ContributionTiming,
+    ) -> Self {
+        self.late_contribution = late_contribution;
+        self.contribution_timing = contribution_timing;
+        self
+    }
+
+    fn with_cash_flows(mut self, cash_flows: Vec<(f64, f64)>) -> Self {
+        self.cash_flows = cash_flows;
+        self
+    }
+
+    /// Return after the annual fees and the taxes on dividends
+    fn net_return(&self) -> f64 {
+        if 1.0 + self.return_rate > 0.0 {
+            (1.0 + self.return_rate)
+                * (1.0 - self.annual_fee_rate - self.dividend_yield * self.taxes.dividend_tax_rate)
+                - 1.0
+        } else {
+            self.return_rate
+        }
+    }
+
+    fn with_holdings(mut self, holdings: Vec<(String, f64)>) -> Self {
+        self.holdings = holdings;
+        self
+    }
+
+    /// `brokerage_fees` were already paid from the initial balance, while `annual_fee_rate` is
+    /// charged on the balance after applying the returns
+    fn with_fees(mut self, brokerage_fees: f64, annual_fee_rate: f64) -> Self {
+        self.brokerage_fees = brokerage_fees;
+        self.annual_fee_rate = annual_fee_rate;
+        self
+    }
+
+    /// Dividends are only tracked in taxable accounts, the only ones taxing them
+    fn with_taxes(
+        mut self,
+        taxes: Taxes,
+        periods: usize,
+        cost_basis: f64,
+        withdrawal_tax: f64,
+    ) -> Self {
+        self.taxes = taxes;
+        self.dividend_yield = match taxes.account {
+            AccountType::Taxable => taxes.dividend_yield / periods as f64,
+            _ => 0.0,
+        };
+        self.cost_basis = cost_basis;
+        self.withdrawal_tax = withdrawal_tax;
+        self
+    }
+
+    fn dividends(&self) -> f64 {
+        self.balance_after_returns().max(0.0) * self.dividend_yield
+    }
+
+    fn dividend_taxes(&self) -> f64 {
+        self.dividends() * self.taxes.dividend_tax_rate
+    }
+
+    fn taxes_paid(&self) -> f64 {
+        self.dividend_taxes() + self.withdrawal_tax
+    }
+
+    /// Dividends are reinvested after taxes, increasing the cost basis
+    fn end_cost_basis(&self) -> f64 {
+        self.cost_basis + self.dividends() - self.dividend_taxes()
+    }
+
+    fn after_tax_final_balance(&self) -> f64 {
+        self.taxes
+            .after_tax_balance(self.final_balance(), self.end_cost_basis())
+    }
+
+    fn fees_paid(&self) -> f64 {
+        self.brokerage_fees + self.annual_fees()
+    }
+
+    fn annual_fees(&self) -> f64 {
+        self.balance_after_returns().max(0.0) * self.annual_fee_rate
+    }
+
+    /// A contribution made in the middle of the period earns half of the period's return
+    fn balance_after_returns(&self) -> f64 {
+        let late_contribution = match self.contribution_timing {
+            ContributionTiming::Middle => {
+                self.late_contribution * (1.0 + self.return_rate).max(0.0).sqrt()
+            }
+            _ => 0.0,
+        };
+        self.initial_balance + (self.initial_balance * self.return_rate) + late_contribution
+    }
+
+    /// `price_level` is the accumulated inflation at the start of the year
+    fn with_inflation(
+        mut self,
+        inflation_rate: f64,
+        price_level: f64,
+    ) -> Result<Self, error::TypeError> {
+        if inflation_rate.is_nan() || price_level.is_nan() {
+            return Err(error::TypeError::NaNInvalid);
+        }
+        self.inflation_rate = inflation_rate;
+        self.price_level = price_level;
+        Ok(self)
+    }
+
+    fn end_price_level(&self) -> f64 {
+        self.price_level * (1.0 + self.inflation_rate)
+    }
+
+    fn with_withdrawal(mut self, withdrawal: f64, depleted: bool) -> Self {
+        self.withdrawal = withdrawal;
+        self.depleted = depleted;
+        self
+    }
+
     pub fn result(&self) -> InvestmentSnapshotResult {
-        InvestmentSnapshotResult {
-            year: self.year,
-            net_contribution: self.net_contribution,
-            initial_balance: self.initial_balance,
-            return_rate: self.return_rate,
-            final_balance: self.final_balance(),
+        year_result(std::slice::from_ref(self))
+    }
+
+    /// A contribution made at the end of the period neither earns returns nor pays fees
+    pub fn final_balance(&self) -> f64 {
+        let late_contribution = match self.contribution_timing {
+            ContributionTiming::End => self.late_contribution,
+            _ => 0.0,
+        };
+        self.balance_after_returns() - self.annual_fees() - self.dividend_taxes()
+            + late_contribution
+    }
+}
+
+/// Aggregates the periods of every year into a single snapshot
+pub fn annual_results(snapshots: &[InvestmentSnapshot]) -> Vec<InvestmentSnapshotResult> {
+    let mut results = Vec::new();
+    let mut start = 0;
+    for end in 1..=snapshots.len() {
+        if end == snapshots.len() || snapshots[end].year != snapshots[start].year {
+            results.push(year_result(&snapshots[start..end]));
+            start = end;
         }
     }
+    results
+}
 
-    fn final_balance(&self) -> f64 {
-        self.initial_balance + (self.initial_balance * self.return_rate)
+/// `periods` are the consecutive periods of a year
+fn year_result(periods: &[InvestmentSnapshot]) -> InvestmentSnapshotResult {
+    let first = &periods[0];
+    let last = &periods[periods.len() - 1];
+    let compound = |rate: fn(&InvestmentSnapshot) -> f64| {
+        periods
+            .iter()
+            .map(|period| 1.0 + rate(period))
+            .product::<f64>()
+            - 1.0
+    };
+
+    InvestmentSnapshotResult {
+        year: first.year,
+        net_contribution: last.net_contribution,
+        initial_balance: first.initial_balance,
+        real_initial_balance: first.initial_balance / first.price_level,
+        return_rate: compound(|period| period.return_rate),
+        final_balance: last.final_balance(),
+        real_final_balance: last.final_balance() / last.end_price_level(),
+        after_tax_final_balance: last.after_tax_final_balance(),
+        inflation_rate: last.end_price_level() / first.price_level - 1.0,
+        fees_paid: periods.iter().map(|period| period.fees_paid()).sum(),
+        withdrawal: periods.iter().map(|period| period.withdrawal).sum(),
+        taxes_paid: periods.iter().map(|period| period.taxes_paid()).sum(),
+        depleted: periods.iter().any(|period| period.depleted),
+        holdings: last
+            .holdings
+            .iter()
+            .map(|(name, weight)| AssetHolding {
+                name: name.clone(),
+                weight: *weight,
+                balance: weight * last.final_balance(),
+            })
+            .collect(),
+        net_return: compound(|period| period.net_return()),
+        cash_flows: periods
+            .iter()
+            .flat_map(|period| period.cash_flows.iter().copied())
+            .filter(|(_, amount)| *amount != 0.0)
+            .collect(),
     }
 }
 
@@ -137,11 +802,57 @@ pub struct InvestmentSnapshotResult {
     year: usize,
     net_contribution: PositiveFloat,
     initial_balance: f64,
+    real_initial_balance: f64,
     return_rate: f64,
     final_balance: f64,
+    real_final_balance: f64,
+    after_tax_final_balance: f64,
+    inflation_rate: f64,
+    fees_paid: f64,
+    withdrawal: f64,
+    /// Taxes on dividends and withdrawals
+    taxes_paid: f64,
+    depleted: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    holdings: Vec<AssetHolding>,
+    #[serde(skip)]
+    net_return: f64,
+    /// Time in years and amount of the money taken (positive) or added (negative)
+    #[serde(skip)]
+    cash_flows: Vec<(f64, f64)>,
+}
+
+impl InvestmentSnapshotResult {
+    pub fn year(&self) -> usize {
+        self.year
+    }
+
+    pub fn net_contribution(&self) -> f64 {
+        self.net_contribution.0
+    }
+
+    pub fn final_balance(&self) -> f64 {
+        self.final_balance
+    }
+
+    pub fn fees_paid(&self) -> f64 {
+        self.fees_paid
+    }
+
+    pub fn depleted(&self) -> bool {
+        self.depleted
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct AssetHolding {
+    name: String,
+    weight: f64,
+    balance: f64,
 }
 
 #[cfg(test)]
+#[allow(clippy::needless_return, clippy::legacy_numeric_constants)]
 mod investment_status_tests {
     use super::InvestmentSnapshot;
     use crate::types;
@@ -222,7 +933,9 @@ mod investment_status_tests {
 #[cfg(test)]
 mod test_investment {
     use super::Investment;
+    use crate::distributions::InMemoryRepository;
     use crate::types;
+    #[allow(unused_imports)]
     use assert_float_eq::{afe_is_f64_near, afe_near_error_msg, assert_f64_near};
 
     #[test]
@@ -231,7 +944,9 @@ mod test_investment {
             types::PositiveFloat::try_from(10000.0).unwrap(),
             3,
             types::AnnualContribution::Single(types::PositiveFloat(0.0)).to_annual_contributions(3),
-            types::Interest::Single(0.05).to_interest_rates(3),
+            types::Interest::Single(0.05)
+                .to_interest_rates(3, &InMemoryRepository::default(), &mut rand::thread_rng())
+                .unwrap(),
         );
         let investment_results = investment.simulate().unwrap();
         let expected: [f64; 3] = [10500.0, 11025.0, 11576.25];
@@ -274,4 +989,364 @@ mod test_investment {
             assert_f64_near!(result.final_balance(), expected[i]);
         }
     }
+
+    #[test]
+    fn test_investment_simulation_with_fixed_withdrawal_depletion() {
+        let investment = Investment::new(
+            types::PositiveFloat::try_from(10000.0).unwrap(),
+            4,
+            types::AnnualContribution::Single(types::PositiveFloat(0.0)).to_annual_contributions(4),
+            vec![0.0, 0.0, 0.0, 0.0],
+        )
+        .with_withdrawal(Some(types::Withdrawal {
+            start_year: 1,
+            strategy: types::WithdrawalStrategy::Fixed {
+                amount: types::PositiveFloat(4000.0),
+            },
+        }));
+        let investment_results = investment.simulate().unwrap();
+        let expected: [f64; 4] = [10000.0, 6000.0, 2000.0, 0.0];
+
+        for (i, result) in investment_results.iter().enumerate() {
+            assert_f64_near!(result.final_balance(), expected[i]);
+        }
+        assert_f64_near!(investment_results[3].withdrawal, 2000.0);
+
+        let results: Vec<super::InvestmentSnapshotResult> = investment_results
+            .iter()
+            .map(|snapshot| snapshot.result())
+            .collect();
+        let investment_result = super::get_investment_result(&results, 0.0).unwrap();
+        assert_eq!(investment_result.depletion_year, Some(3));
+        assert_f64_near!(investment_result.total_withdrawals, 10000.0);
+    }
+
+    #[test]
+    fn test_investment_simulation_with_percentage_withdrawal() {
+        let investment = Investment::new(
+            types::PositiveFloat::try_from(100000.0).unwrap(),
+            2,
+            types::AnnualContribution::Single(types::PositiveFloat(0.0)).to_annual_contributions(2),
+            vec![0.05, 0.05],
+        )
+        .with_withdrawal(Some(types::Withdrawal {
+            start_year: 0,
+            strategy: types::WithdrawalStrategy::Percentage { rate: 0.04 },
+        }));
+        let investment_results = investment.simulate().unwrap();
+        let expected: [f64; 2] = [100800.0, 101606.4];
+
+        for (i, result) in investment_results.iter().enumerate() {
+            assert_f64_near!(result.final_balance(), expected[i]);
+        }
+    }
+
+    #[test]
+    fn test_investment_simulation_with_inflation() {
+        let investment = Investment::new(
+            types::PositiveFloat::try_from(10000.0).unwrap(),
+            2,
+            types::AnnualContribution::Single(types::PositiveFloat(1000.0))
+                .to_annual_contributions(2),
+            vec![0.05, 0.05],
+        )
+        .with_inflation(vec![0.02, 0.03], true);
+        let investment_results: Vec<super::InvestmentSnapshotResult> = investment
+            .simulate()
+            .unwrap()
+            .iter()
+            .map(|snapshot| snapshot.result())
+            .collect();
+
+        // The second contribution is indexed to the 2% inflation of the first year
+        assert_f64_near!(investment_results[0].final_balance, 11550.0);
+        assert_f64_near!(investment_results[0].real_final_balance, 11550.0 / 1.02);
+        assert_f64_near!(investment_results[1].final_balance, 13198.5);
+        assert_f64_near!(investment_results[1].real_final_balance, 13198.5 / 1.0506);
+
+        let investment_result = super::get_investment_result(&investment_results, 0.0).unwrap();
+        assert_f64_near!(investment_result.real_final_balance, 13198.5 / 1.0506);
+    }
+
+    #[test]
+    fn test_monthly_simulation() {
+        let investment = Investment::new(
+            types::PositiveFloat::try_from(10000.0).unwrap(),
+            2,
+            types::AnnualContribution::Single(types::PositiveFloat(1200.0))
+                .to_annual_contributions(2),
+            vec![0.1, 0.1],
+        )
+        .with_fees(types::Fees {
+            expense_ratio: 0.01,
+            ..Default::default()
+        })
+        .with_granularity(types::Granularity::Monthly);
+        let snapshots = investment.simulate().unwrap();
+        assert_eq!(snapshots.len(), 24);
+
+        // Every monthly contribution of 100 grows for the rest of the year
+        let monthly_growth = 1.1f64.powf(1.0 / 12.0) * 0.99f64.powf(1.0 / 12.0);
+        let contributions: f64 = (1..=12)
+            .map(|months| 100.0 * monthly_growth.powi(months))
+            .sum();
+        let first_year = 10000.0 * 1.1 * 0.99 + contributions;
+        let results = super::annual_results(&snapshots);
+        assert_eq!(results.len(), 2);
+        assert!((results[0].final_balance - first_year).abs() < 1e-6);
+        assert!((results[0].return_rate - 0.1).abs() < 1e-12);
+        assert!(
+            (results[1].final_balance - (first_year * 1.1 * 0.99 + contributions)).abs() < 1e-6
+        );
+        assert_f64_near!(results[1].net_contribution.0, 12400.0);
+    }
+
+    #[test]
+    fn test_monthly_returns_are_used_when_available() {
+        let mut return_rates = vec![0.0; 12];
+        return_rates[5] = 0.5;
+        let investment = Investment::new(
+            types::PositiveFloat::try_from(10000.0).unwrap(),
+            1,
+            types::AnnualContribution::Single(types::PositiveFloat(0.0)).to_annual_contributions(1),
+            return_rates,
+        )
+        .with_granularity(types::Granularity::Monthly);
+        let snapshots = investment.simulate().unwrap();
+
+        assert_f64_near!(snapshots[4].final_balance(), 10000.0);
+        assert_f64_near!(snapshots[5].final_balance(), 15000.0);
+        assert_f64_near!(super::annual_results(&snapshots)[0].final_balance, 15000.0);
+    }
+
+    #[test]
+    fn test_contribution_timing() {
+        let final_balance = |timing: types::ContributionTiming| {
+            let investment = Investment::new(
+                types::PositiveFloat::try_from(0.0).unwrap(),
+                3,
+                types::AnnualContribution::Single(types::PositiveFloat(1000.0))
+                    .to_annual_contributions(3),
+                vec![0.05, 0.05, 0.05],
+            )
+            .with_contribution_timing(timing);
+            let results = super::annual_results(&investment.simulate().unwrap());
+            let result = super::get_investment_result(&results, 0.0).unwrap();
+            let money_weighted_return = result.metrics().money_weighted_return().unwrap();
+            assert!((money_weighted_return - 0.05).abs() < 1e-8);
+            result.final_balance
+        };
+
+        // Annuity-due and ordinary annuity future values
+        let ordinary_annuity = 1000.0 * (1.05f64.powi(3) - 1.0) / 0.05;
+        assert_f64_near!(
+            final_balance(types::ContributionTiming::Start),
+            ordinary_annuity * 1.05,
+            8
+        );
+        assert_f64_near!(
+            final_balance(types::ContributionTiming::End),
+            ordinary_annuity,
+            8
+        );
+        assert_f64_near!(
+            final_balance(types::ContributionTiming::Middle),
+            ordinary_annuity * 1.05f64.sqrt(),
+            8
+        );
+    }
+
+    #[test]
+    fn test_investment_result_metrics() {
+        let investment = Investment::new(
+            types::PositiveFloat::try_from(10000.0).unwrap(),
+            2,
+            types::AnnualContribution::Single(types::PositiveFloat(1000.0))
+                .to_annual_contributions(2),
+            vec![0.1, 0.1],
+        );
+        let investment_results: Vec<super::InvestmentSnapshotResult> = investment
+            .simulate()
+            .unwrap()
+            .iter()
+            .map(|snapshot| snapshot.result())
+            .collect();
+        let investment_result = super::get_investment_result(&investment_results, 0.0).unwrap();
+
+        // 11000 * 1.1 = 12100, (12100 + 1000) * 1.1 = 14410
+        assert_f64_near!(investment_result.total_gains, 14410.0 - 12000.0);
+        assert_f64_near!(investment_result.metrics().cagr(), 0.1, 10);
+        let money_weighted_return = investment_result.metrics().money_weighted_return().unwrap();
+        assert!((money_weighted_return - 0.1).abs() < 1e-8);
+    }
+
+    #[test]
+    fn test_investment_simulation_with_taxes() {
+        let simulate = |account: types::AccountType| {
+            let investment = Investment::new(
+                types::PositiveFloat::try_from(10000.0).unwrap(),
+                2,
+                types::AnnualContribution::Single(types::PositiveFloat(0.0))
+                    .to_annual_contributions(2),
+                vec![0.1, 0.1],
+            )
+            .with_withdrawal(Some(types::Withdrawal {
+                start_year: 1,
+                strategy: types::WithdrawalStrategy::Fixed {
+                    amount: types::PositiveFloat(1000.0),
+                },
+            }))
+            .with_taxes(types::Taxes {
+                account,
+                capital_gains_rate: 0.2,
+                dividend_yield: 0.02,
+                dividend_tax_rate: 0.5,
+                income_tax_rate: 0.4,
+            });
+            let investment_results = super::annual_results(&investment.simulate().unwrap());
+            super::get_investment_result(&investment_results, 0.0).unwrap()
+        };
+
+        // Half of the 220 of dividends are taxed, and the reinvested 110 raise the cost basis.
+        // The withdrawal realises the gains of its share of the balance.
+        let withdrawal_tax = (1000.0 - 10110.0 * 1000.0 / 10890.0) * 0.2;
+        let cost_basis = 10110.0 * (1.0 - 1000.0 / 10890.0) + 9890.0 * 1.1 * 0.01;
+        let final_balance = 9890.0 * 1.1 * 0.99;
+        let taxable = simulate(types::AccountType::Taxable);
+        assert_f64_near!(taxable.final_balance(), final_balance, 10);
+        assert_f64_near!(
+            taxable.total_taxes,
+            110.0 + withdrawal_tax + 9890.0 * 1.1 * 0.01,
+            10
+        );
+        assert_f64_near!(
+            taxable.after_tax_final_balance(),
+            final_balance - (final_balance - cost_basis) * 0.2,
+            10
+        );
+
+        // (11000 - 1000) * 1.1 = 11000, every withdrawal is taxed as income
+        let deferred = simulate(types::AccountType::TaxDeferred);
+        assert_f64_near!(deferred.final_balance(), 11000.0, 10);
+        assert_f64_near!(deferred.total_taxes, 400.0);
+        assert_f64_near!(deferred.after_tax_final_balance(), 11000.0 * 0.6, 10);
+
+        let free = simulate(types::AccountType::TaxFree);
+        assert_f64_near!(free.total_taxes, 0.0);
+        assert_f64_near!(free.after_tax_final_balance(), 11000.0, 10);
+    }
+
+    #[test]
+    fn test_investment_simulation_with_fees() {
+        let investment = Investment::new(
+            types::PositiveFloat::try_from(10000.0).unwrap(),
+            2,
+            types::AnnualContribution::Single(types::PositiveFloat(1000.0))
+                .to_annual_contributions(2),
+            vec![0.1, 0.1],
+        )
+        .with_fees(types::Fees {
+            expense_ratio: 0.01,
+            brokerage_fee: types::BrokerageFee {
+                flat: 5.0,
+                rate: 0.001,
+            },
+            custody_fee: 0.0,
+        });
+        let investment_results: Vec<super::InvestmentSnapshotResult> = investment
+            .simulate()
+            .unwrap()
+            .iter()
+            .map(|snapshot| snapshot.result())
+            .collect();
+
+        // Year 1: (10000 - 15 + 1000 - 6) * 1.1 = 12076.9, minus 1% TER
+        assert_f64_near!(investment_results[0].fees_paid, 21.0 + 120.769);
+        assert_f64_near!(investment_results[0].final_balance, 11956.131);
+        // Year 2: (11956.131 + 1000 - 6) * 1.1 = 14245.1441, minus 1% TER
+        assert_f64_near!(investment_results[1].fees_paid, 6.0 + 142.451441);
+        assert_f64_near!(investment_results[1].final_balance, 14102.692659);
+
+        let investment_result = super::get_investment_result(&investment_results, 0.0).unwrap();
+        assert_f64_near!(
+            investment_result.total_fees,
+            21.0 + 120.769 + 6.0 + 142.451441
+        );
+    }
+
+    fn portfolio_investment(rebalancing: types::Rebalancing) -> Investment {
+        let portfolio = super::Portfolio::new(
+            vec![
+                super::PortfolioAsset::new("stocks".to_string(), 0.5, vec![0.2, 0.2]),
+                super::PortfolioAsset::new("bonds".to_string(), 0.5, vec![0.0, 0.0]),
+            ],
+            rebalancing,
+        )
+        .unwrap();
+
+        Investment::new(
+            types::PositiveFloat::try_from(10000.0).unwrap(),
+            2,
+            types::AnnualContribution::Single(types::PositiveFloat(0.0)).to_annual_contributions(2),
+            Vec::new(),
+        )
+        .with_portfolio(Some(portfolio))
+    }
+
+    #[test]
+    fn test_portfolio_simulation_without_rebalancing() {
+        let investment_results: Vec<super::InvestmentSnapshotResult> =
+            portfolio_investment(types::Rebalancing::Never)
+                .simulate()
+                .unwrap()
+                .iter()
+                .map(|snapshot| snapshot.result())
+                .collect();
+
+        // Stocks grow 6000 -> 7200, bonds stay at 5000
+        assert_f64_near!(investment_results[0].final_balance, 11000.0);
+        assert_f64_near!(investment_results[1].final_balance, 12200.0);
+        assert_f64_near!(investment_results[1].holdings[0].balance, 7200.0);
+        assert_f64_near!(investment_results[1].holdings[1].balance, 5000.0);
+    }
+
+    #[test]
+    fn test_portfolio_simulation_with_annual_rebalancing() {
+        let investment_results: Vec<super::InvestmentSnapshotResult> =
+            portfolio_investment(types::Rebalancing::Annually)
+                .simulate()
+                .unwrap()
+                .iter()
+                .map(|snapshot| snapshot.result())
+                .collect();
+
+        // Back to 5500 / 5500 at the start of the second year
+        assert_f64_near!(investment_results[1].final_balance, 12100.0);
+        assert_f64_near!(investment_results[1].holdings[0].balance, 6600.0);
+        assert_f64_near!(investment_results[1].holdings[1].balance, 5500.0);
+    }
+
+    #[test]
+    fn test_portfolio_with_invalid_weights() {
+        let portfolio = super::Portfolio::new(
+            vec![super::PortfolioAsset::new(
+                "stocks".to_string(),
+                0.0,
+                vec![0.2],
+            )],
+            types::Rebalancing::Never,
+        );
+        assert!(portfolio.is_err());
+    }
+
+    #[test]
+    fn test_investment_simulation_with_missing_rates() {
+        let investment = Investment::new(
+            types::PositiveFloat::try_from(10000.0).unwrap(),
+            3,
+            types::AnnualContribution::Single(types::PositiveFloat(0.0)).to_annual_contributions(3),
+            vec![0.05],
+        );
+        assert!(investment.simulate().is_err());
+    }
 }
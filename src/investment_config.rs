@@ -1,9 +1,154 @@
+use crate::distributions;
+use crate::error;
+use crate::investment;
 use crate::types;
+use rand::SeedableRng;
 
-#[derive(serde::Deserialize)]
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
 pub struct Configuration {
     pub deposit: usize,
-    pub return_rates: types::Interest,
+    pub return_rates: Option<types::Interest>,
     pub years: usize,
     pub annual_contributions: types::AnnualContribution,
+    pub simulations: Option<usize>,
+    pub seed: Option<u64>,
+    pub withdrawals: Option<types::Withdrawal>,
+    pub inflation: Option<types::Interest>,
+    #[serde(default)]
+    pub index_contributions_to_inflation: bool,
+    #[serde(default)]
+    pub fees: types::Fees,
+    #[serde(default)]
+    pub taxes: types::Taxes,
+    pub portfolio: Option<types::Portfolio>,
+    pub target_balance: Option<f64>,
+    /// Used by the Sharpe and Sortino ratios
+    #[serde(default)]
+    pub risk_free_rate: f64,
+    #[serde(default)]
+    pub granularity: types::Granularity,
+    #[serde(default)]
+    pub contribution_timing: types::ContributionTiming,
+}
+
+impl Configuration {
+    /// Seed used to sample the simulation, a random one is drawn when none is configured
+    pub fn seed(&self) -> u64 {
+        self.seed.unwrap_or_else(rand::random)
+    }
+
+    pub fn investment<R: rand::Rng + ?Sized>(
+        &self,
+        distributions: &dyn distributions::DistributionRepository,
+        rng: &mut R,
+    ) -> Result<investment::Investment, error::SimulationError> {
+        let mut interests: Vec<(&types::Interest, distributions::Series)> =
+            match (&self.return_rates, &self.portfolio) {
+                (_, Some(portfolio)) => portfolio
+                    .assets
+                    .iter()
+                    .map(|asset| (&asset.return_rates, distributions::Series::Returns))
+                    .collect(),
+                (Some(return_rates), None) => vec![(return_rates, distributions::Series::Returns)],
+                (None, None) => return Err(error::SimulationError::MissingReturnRates),
+            };
+        interests.extend(
+            self.inflation
+                .iter()
+                .map(|inflation| (inflation, distributions::Series::Inflation)),
+        );
+        let mut rates = types::sampled_rates(&interests, self.years, distributions, rng)?;
+        let inflation_rates = match self.inflation {
+            Some(_) => rates.pop().unwrap_or_default(),
+            None => Vec::new(),
+        };
+        let (return_rates, portfolio) = match &self.portfolio {
+            Some(portfolio) => {
+                let assets = portfolio
+                    .assets
+                    .iter()
+                    .zip(rates)
+                    .map(|(asset, return_rates)| {
+                        investment::PortfolioAsset::new(
+                            asset.name.clone(),
+                            asset.weight,
+                            return_rates,
+                        )
+                    })
+                    .collect();
+                (
+                    Vec::new(),
+                    Some(investment::Portfolio::new(assets, portfolio.rebalancing)?),
+                )
+            }
+            None => (rates.pop().unwrap_or_default(), None),
+        };
+
+        Ok(investment::Investment::new(
+            types::PositiveFloat::try_from(self.deposit as f64)?,
+            self.years,
+            self.annual_contributions
+                .to_annual_contributions(self.years),
+            return_rates,
+        )
+        .with_withdrawal(self.withdrawals.clone())
+        .with_inflation(
+            inflation_rates,
+            self.index_contributions_to_inflation || self.annual_contributions.follows_inflation(),
+        )
+        .with_fees(self.fees)
+        .with_taxes(self.taxes)
+        .with_portfolio(portfolio)
+        .with_granularity(self.granularity)
+        .with_contribution_timing(self.contribution_timing))
+    }
+
+    /// Simulates a single path, the same seed and configuration always give the same result
+    pub fn simulate(
+        &self,
+        seed: u64,
+        distributions: &dyn distributions::DistributionRepository,
+    ) -> Result<investment::Simulation, error::SimulationError> {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+        let snapshots =
+            investment::annual_results(&self.investment(distributions, &mut rng)?.simulate()?);
+        let result =
+            investment::get_investment_result(&snapshots, self.risk_free_rate)?.with_seed(seed);
+
+        Ok(investment::Simulation { snapshots, result })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Configuration;
+    use crate::distributions::{CsvRepository, DEFAULT_DIRECTORY};
+
+    #[test]
+    fn test_same_seed_gives_identical_snapshots() {
+        let config: Configuration = serde_json::from_value(serde_json::json!({
+            "deposit": 10000,
+            "years": 20,
+            "return_rates": "msci_world",
+            "annual_contributions": 1200.0,
+        }))
+        .unwrap();
+
+        let repository = CsvRepository::load(DEFAULT_DIRECTORY).unwrap();
+        let first = config.simulate(1234, &repository).unwrap();
+        let second = config.simulate(1234, &repository).unwrap();
+
+        assert_eq!(
+            serde_json::to_value(&first.snapshots).unwrap(),
+            serde_json::to_value(&second.snapshots).unwrap()
+        );
+        assert_eq!(
+            serde_json::to_value(&first.result).unwrap(),
+            serde_json::to_value(&second.result).unwrap()
+        );
+        assert_eq!(
+            serde_json::to_value(&first.result).unwrap()["seed"],
+            serde_json::json!(1234)
+        );
+    }
 }
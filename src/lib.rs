@@ -1,8 +1,19 @@
+pub mod backtest;
 pub mod cli;
+pub mod compare;
 pub mod configuration;
 pub mod distributions;
 pub mod error;
 pub mod investment;
 pub mod investment_config;
+pub mod metrics;
+pub mod monte_carlo;
+pub mod persistence;
+pub mod return_models;
+pub mod sensitivity;
 pub mod server;
+pub mod solver;
+pub mod statistics;
+pub mod sweep;
 pub mod types;
+pub mod validation;
@@ -1,9 +1,15 @@
-use clap::{arg, command, Parser, ValueEnum};
+#[allow(unused_imports)]
+use clap::{arg, command, Parser, Subcommand, ValueEnum};
 
 use fund_simulator_rs::error;
 use fund_simulator_rs::cli;
 use fund_simulator_rs::server;
 use fund_simulator_rs::configuration;
+use fund_simulator_rs::distributions;
+use fund_simulator_rs::sensitivity;
+use fund_simulator_rs::solver;
+use fund_simulator_rs::statistics;
+use fund_simulator_rs::sweep;
 
 #[derive(Clone, ValueEnum, Debug, PartialEq)]
 enum AppMode {
@@ -11,29 +17,148 @@ enum AppMode {
     Cli,
 }
 
+#[derive(Subcommand, Debug)]
+enum Command {
+    #[command(about = "Replay history once for every possible start year")]
+    Rolling {
+        #[arg(help = "Configuration file")]
+        config_file: String,
+    },
+    #[command(about = "Find the value of an unknown that reaches the target balance")]
+    Solve {
+        #[arg(help = "Configuration file")]
+        config_file: String,
+        #[arg(long = "for", help = "Unknown to solve for")]
+        solve_for: solver::Unknown,
+        #[arg(
+            long,
+            help = "Share of the Monte Carlo paths that must reach the target"
+        )]
+        probability: Option<f64>,
+    },
+    #[command(about = "Rank how much every input moves the final balance")]
+    Sensitivity {
+        #[arg(help = "Configuration file")]
+        config_file: String,
+        #[command(flatten)]
+        variations: sensitivity::Variations,
+    },
+    #[command(about = "Simulate every combination of the values listed in `sweep`")]
+    Sweep {
+        #[arg(help = "Configuration file")]
+        config_file: String,
+        #[arg(long, help = "Output format", default_value = "json")]
+        format: sweep::Format,
+    },
+    #[command(about = "Compare several configurations following the same return path")]
+    Compare {
+        #[arg(required = true, num_args = 2.., help = "Configuration files")]
+        config_files: Vec<String>,
+    },
+    #[command(about = "Inspect the historical distributions")]
+    Distributions {
+        #[command(subcommand)]
+        command: DistributionsCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum DistributionsCommand {
+    #[command(about = "Descriptive statistics of a distribution")]
+    Stats {
+        #[arg(help = "Distribution name")]
+        name: String,
+        #[arg(long, help = "Number of histogram bins", default_value_t = statistics::DEFAULT_HISTOGRAM_BINS)]
+        bins: usize,
+    },
+}
+
 #[derive(Parser, Debug)]
-#[command(about = "Simulate index funds behaviour!")]
+#[command(
+    about = "Simulate index funds behaviour!",
+    subcommand_negates_reqs = true
+)]
 struct Args {
-    #[arg(short, long, help = "Application mode")]
-    mode: AppMode,
+    #[arg(short, long, help = "Application mode", required = true)]
+    mode: Option<AppMode>,
     #[arg(short, long, help = "Configuration file", required = false)]
     config_file: Option<String>,
+    #[arg(
+        short,
+        long,
+        help = "Seed for the random number generator",
+        required = false,
+        global = true
+    )]
+    seed: Option<u64>,
+    #[arg(
+        long,
+        help = "Directory with the distribution CSV files",
+        default_value = distributions::DEFAULT_DIRECTORY,
+        global = true
+    )]
+    distributions_dir: String,
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
 #[tokio::main]
 async fn main() -> Result<(), error::ApplicationError> {
     let args = Args::parse();
-    if args.mode == AppMode::Cli && args.config_file.is_none() {
+    if let Some(command) = args.command {
+        let repository = distributions::CsvRepository::load(&args.distributions_dir)?;
+        match command {
+            Command::Rolling { config_file } => {
+                cli::run_cli_rolling_analysis(config_file, args.seed, &repository)
+            }
+            Command::Solve {
+                config_file,
+                solve_for,
+                probability,
+            } => cli::run_cli_solver(
+                config_file,
+                solver::Goal {
+                    solve_for,
+                    probability,
+                },
+                args.seed,
+                &repository,
+            ),
+            Command::Sensitivity {
+                config_file,
+                variations,
+            } => cli::run_cli_sensitivity_analysis(config_file, variations, args.seed, &repository),
+            Command::Sweep {
+                config_file,
+                format,
+            } => cli::run_cli_sweep(config_file, format, args.seed, &repository),
+            Command::Compare { config_files } => {
+                cli::run_cli_comparison(config_files, args.seed, &repository)
+            }
+            Command::Distributions {
+                command: DistributionsCommand::Stats { name, bins },
+            } => cli::run_cli_distribution_statistics(name, bins, &repository),
+        }
+        return Ok(());
+    }
+    if args.mode == Some(AppMode::Cli) && args.config_file.is_none() {
         eprintln!("Error: `config_file` is required when `mode` is set to `Cli`");
     }
 
-    match args.mode {
-        AppMode::Cli => cli::run_cli_simulation(args.config_file.unwrap()),
+    match args.mode.unwrap() {
+        AppMode::Cli => {
+            let repository = distributions::CsvRepository::load(&args.distributions_dir)?;
+            cli::run_cli_simulation(args.config_file.unwrap(), args.seed, &repository)
+        }
         AppMode::Server => {
             let configuration = configuration::Configuration::load()?;
             let pool = sqlx::PgPool::connect(&configuration.get_postgres_url()).await?;
-            let server =
-                server::Server::new("0.0.0.0".to_string(), configuration.application_port, &pool);
+            let server = server::Server::new(
+                "0.0.0.0".to_string(),
+                configuration.application_port,
+                &pool,
+                args.distributions_dir,
+            );
             server.serve().await?;
         }
     }
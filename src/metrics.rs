@@ -0,0 +1,239 @@
+/// Largest fall of the time-weighted wealth, from the start of `peak_year` to the end of
+/// `trough_year`
+#[derive(serde::Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct MaxDrawdown {
+    drawdown: f64,
+    peak_year: usize,
+    trough_year: usize,
+}
+
+/// Performance of a simulated path. The yearly returns are net of the annual fees and the ratios
+/// are `None` when they aren't defined, e.g. with constant returns.
+#[derive(serde::Serialize, Debug, Clone, PartialEq)]
+pub struct PerformanceMetrics {
+    cagr: f64,
+    money_weighted_return: Option<f64>,
+    volatility: f64,
+    max_drawdown: Option<MaxDrawdown>,
+    risk_free_rate: f64,
+    sharpe_ratio: Option<f64>,
+    sortino_ratio: Option<f64>,
+}
+
+impl PerformanceMetrics {
+    /// `cash_flows` are the time in years and amount of the money added (negative) or taken
+    /// (positive), while the final balance is taken out at the time of `final_balance`
+    pub fn new(
+        returns: &[f64],
+        cash_flows: &[(f64, f64)],
+        final_balance: (f64, f64),
+        risk_free_rate: f64,
+    ) -> Self {
+        let volatility = standard_deviation(returns);
+        let mean = mean(returns);
+        let downside = downside_deviation(returns, risk_free_rate);
+        let ratio = |deviation: f64| {
+            if deviation > 0.0 {
+                Some((mean - risk_free_rate) / deviation)
+            } else {
+                None
+            }
+        };
+
+        let mut flows = cash_flows.to_vec();
+        flows.push(final_balance);
+
+        PerformanceMetrics {
+            cagr: cagr(returns),
+            money_weighted_return: internal_rate_of_return(&flows),
+            volatility,
+            max_drawdown: max_drawdown(returns),
+            risk_free_rate,
+            sharpe_ratio: ratio(volatility),
+            sortino_ratio: ratio(downside),
+        }
+    }
+
+    pub fn cagr(&self) -> f64 {
+        self.cagr
+    }
+
+    pub fn money_weighted_return(&self) -> Option<f64> {
+        self.money_weighted_return
+    }
+
+    pub fn volatility(&self) -> f64 {
+        self.volatility
+    }
+
+    pub fn max_drawdown(&self) -> Option<f64> {
+        self.max_drawdown
+            .as_ref()
+            .map(|max_drawdown| max_drawdown.drawdown)
+    }
+
+    pub fn sharpe_ratio(&self) -> Option<f64> {
+        self.sharpe_ratio
+    }
+
+    pub fn sortino_ratio(&self) -> Option<f64> {
+        self.sortino_ratio
+    }
+}
+
+fn mean(values: &[f64]) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+/// Sample standard deviation, 0 with less than two values
+fn standard_deviation(values: &[f64]) -> f64 {
+    if values.len() < 2 {
+        return 0.0;
+    }
+    let mean = mean(values);
+    let variance =
+        values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (values.len() - 1) as f64;
+    variance.sqrt()
+}
+
+/// Deviation of the returns below `target`
+fn downside_deviation(values: &[f64], target: f64) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    let squares: f64 = values.iter().map(|v| (v - target).min(0.0).powi(2)).sum();
+    (squares / values.len() as f64).sqrt()
+}
+
+/// Compound annual growth rate, the geometric mean of the returns
+pub fn cagr(returns: &[f64]) -> f64 {
+    if returns.is_empty() {
+        return 0.0;
+    }
+    let growth: f64 = returns.iter().map(|r| 1.0 + r).product();
+    if growth <= 0.0 {
+        return -1.0;
+    }
+    growth.powf(1.0 / returns.len() as f64) - 1.0
+}
+
+fn max_drawdown(returns: &[f64]) -> Option<MaxDrawdown> {
+    let mut worst: Option<MaxDrawdown> = None;
+    let mut peak = 1.0;
+    let mut peak_year = 0;
+    let mut wealth = 1.0;
+    for (year, rate) in returns.iter().enumerate() {
+        wealth *= 1.0 + rate;
+        if wealth >= peak {
+            peak = wealth;
+            peak_year = year + 1;
+            continue;
+        }
+        let drawdown = wealth / peak - 1.0;
+        let is_worst = match worst {
+            Some(worst) => drawdown < worst.drawdown,
+            None => true,
+        };
+        if is_worst {
+            worst = Some(MaxDrawdown {
+                drawdown,
+                peak_year,
+                trough_year: year,
+            });
+        }
+    }
+    worst
+}
+
+const IRR_TOLERANCE: f64 = 1e-10;
+const IRR_MAX_ITERATIONS: usize = 200;
+
+/// Annual rate making the net present value of the cash flows zero, found by bisection. Every
+/// cash flow is its time in years and its amount, so they don't need to be evenly spaced.
+/// `None` when the cash flows don't change sign within the searched rates.
+pub fn internal_rate_of_return(cash_flows: &[(f64, f64)]) -> Option<f64> {
+    let npv = |rate: f64| -> f64 {
+        cash_flows
+            .iter()
+            .map(|(time, flow)| flow / (1.0 + rate).powf(*time))
+            .sum()
+    };
+
+    let mut low = -0.9999;
+    let mut high = 1.0;
+    while npv(low).signum() == npv(high).signum() {
+        if high > 1000.0 {
+            return None;
+        }
+        high *= 2.0;
+    }
+    for _ in 0..IRR_MAX_ITERATIONS {
+        let middle = (low + high) / 2.0;
+        let value = npv(middle);
+        if value.abs() < IRR_TOLERANCE || (high - low) / 2.0 < IRR_TOLERANCE {
+            return Some(middle);
+        }
+        if value.signum() == npv(low).signum() {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+    Some((low + high) / 2.0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{cagr, internal_rate_of_return, PerformanceMetrics};
+    use assert_float_eq::assert_float_absolute_eq;
+
+    #[test]
+    fn test_cagr_is_lower_than_the_average() {
+        // +50% and -50% average 0% but lose a quarter of the money
+        assert_float_absolute_eq!(cagr(&[0.5, -0.5]), 0.75f64.sqrt() - 1.0, 1e-12);
+        assert_float_absolute_eq!(cagr(&[0.05, 0.05, 0.05]), 0.05, 1e-12);
+    }
+
+    #[test]
+    fn test_internal_rate_of_return() {
+        // 1000 invested each year for two years growing 10% a year
+        let rate = internal_rate_of_return(&[
+            (0.0, -1000.0),
+            (1.0, -1000.0),
+            (2.0, 1000.0 * 1.1 * 1.1 + 1000.0 * 1.1),
+        ]);
+        assert_float_absolute_eq!(rate.unwrap(), 0.1, 1e-8);
+
+        // Half a year at 21% a year
+        let rate = internal_rate_of_return(&[(0.0, -1000.0), (0.5, 1100.0)]);
+        assert_float_absolute_eq!(rate.unwrap(), 0.21, 1e-8);
+
+        assert_eq!(
+            internal_rate_of_return(&[(0.0, -1000.0), (1.0, -1000.0)]),
+            None
+        );
+    }
+
+    #[test]
+    fn test_performance_metrics() {
+        let metrics = PerformanceMetrics::new(
+            &[0.2, -0.1, -0.1, 0.3],
+            &[(0.0, -100.0)],
+            (4.0, 100.0 * 1.2 * 0.9 * 0.9 * 1.3),
+            0.02,
+        );
+
+        let drawdown = metrics.max_drawdown.unwrap();
+        assert_eq!((drawdown.peak_year, drawdown.trough_year), (1, 2));
+        assert_float_absolute_eq!(drawdown.drawdown, 0.81 - 1.0, 1e-12);
+        assert_float_absolute_eq!(metrics.money_weighted_return.unwrap(), metrics.cagr, 1e-8);
+        assert!(metrics.sortino_ratio.unwrap() > metrics.sharpe_ratio.unwrap());
+
+        let constant = PerformanceMetrics::new(&[0.05, 0.05], &[(0.0, -100.0)], (2.0, 110.25), 0.0);
+        assert_eq!(constant.sharpe_ratio, None);
+        assert_eq!(constant.max_drawdown, None);
+    }
+}
@@ -0,0 +1,319 @@
+use crate::distributions;
+use crate::error;
+use crate::investment;
+use crate::investment_config;
+use rand::SeedableRng;
+
+pub const DEFAULT_SIMULATIONS: usize = 1000;
+
+#[derive(serde::Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct Percentiles {
+    p5: f64,
+    p25: f64,
+    p50: f64,
+    p75: f64,
+    p95: f64,
+}
+
+impl Percentiles {
+    fn from_values(mut values: Vec<f64>) -> Self {
+        values.sort_by(|a, b| a.total_cmp(b));
+        Percentiles {
+            p5: percentile(&values, 0.05),
+            p25: percentile(&values, 0.25),
+            p50: percentile(&values, 0.50),
+            p75: percentile(&values, 0.75),
+            p95: percentile(&values, 0.95),
+        }
+    }
+}
+
+#[derive(serde::Serialize, Debug)]
+pub struct YearPercentiles {
+    year: usize,
+    balance: Percentiles,
+}
+
+#[derive(serde::Serialize, Debug)]
+pub struct MonteCarloResult {
+    simulations: usize,
+    seed: u64,
+    final_balance: Percentiles,
+    yearly_balances: Vec<YearPercentiles>,
+    success: SuccessResult,
+}
+
+#[derive(serde::Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct YearShare {
+    year: usize,
+    share: f64,
+}
+
+/// Probability of success and ruin of a plan, a path succeeds when it never runs out of money
+#[derive(serde::Serialize, Debug)]
+pub struct SuccessResult {
+    paths: usize,
+    success_rate: f64,
+    ruin_rate: f64,
+    /// Share of the paths running out of money in every year
+    depletion_years: Vec<YearShare>,
+    /// Percentiles of the depletion year among the paths running out of money
+    depletion_year: Option<Percentiles>,
+    median_successful_final_balance: Option<f64>,
+    /// Share of the paths still solvent at the end of every year
+    survival_curve: Vec<YearShare>,
+}
+
+impl SuccessResult {
+    /// `paths` holds the depletion year, if any, and the final balance of every path
+    pub fn from_paths(paths: &[(Option<usize>, f64)], years: usize) -> Self {
+        let total = paths.len().max(1) as f64;
+        let mut depletions = vec![0; years];
+        for year in paths
+            .iter()
+            .filter_map(|(depletion_year, _)| *depletion_year)
+        {
+            if year < years {
+                depletions[year] += 1;
+            }
+        }
+        let mut solvent = paths.len();
+        let survival_curve = depletions
+            .iter()
+            .enumerate()
+            .map(|(year, depleted)| {
+                solvent -= depleted;
+                YearShare {
+                    year,
+                    share: solvent as f64 / total,
+                }
+            })
+            .collect();
+
+        let depleted: Vec<f64> = paths
+            .iter()
+            .filter_map(|(depletion_year, _)| depletion_year.map(|year| year as f64))
+            .collect();
+        let mut successful: Vec<f64> = paths
+            .iter()
+            .filter(|(depletion_year, _)| depletion_year.is_none())
+            .map(|(_, final_balance)| *final_balance)
+            .collect();
+        successful.sort_by(|a, b| a.total_cmp(b));
+        let success_rate = successful.len() as f64 / total;
+
+        SuccessResult {
+            paths: paths.len(),
+            success_rate,
+            ruin_rate: depleted.len() as f64 / total,
+            depletion_years: depletions
+                .iter()
+                .enumerate()
+                .map(|(year, depleted)| YearShare {
+                    year,
+                    share: *depleted as f64 / total,
+                })
+                .collect(),
+            depletion_year: (!depleted.is_empty()).then(|| Percentiles::from_values(depleted)),
+            median_successful_final_balance: (!successful.is_empty())
+                .then(|| percentile(&successful, 0.5)),
+            survival_curve,
+        }
+    }
+
+    pub fn success_rate(&self) -> f64 {
+        self.success_rate
+    }
+}
+
+pub fn run_monte_carlo(
+    config: &investment_config::Configuration,
+    simulations: usize,
+    seed: u64,
+    distributions: &dyn distributions::DistributionRepository,
+) -> Result<MonteCarloResult, error::SimulationError> {
+    if simulations == 0 || config.years == 0 {
+        return Err(error::SimulationError::InvalidInvestmentResults);
+    }
+
+    // A single generator drives every path so the whole run is reproducible from the seed
+    let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+    // One vector of balances per year, each one holding a value per simulated path
+    let mut yearly_balances: Vec<Vec<f64>> = vec![Vec::with_capacity(simulations); config.years];
+    let mut paths: Vec<(Option<usize>, f64)> = Vec::with_capacity(simulations);
+    for _ in 0..simulations {
+        let snapshots =
+            investment::annual_results(&config.investment(distributions, &mut rng)?.simulate()?);
+        for (year, snapshot) in snapshots.iter().enumerate() {
+            yearly_balances[year].push(snapshot.final_balance());
+        }
+        paths.push((
+            snapshots.iter().position(|snapshot| snapshot.depleted()),
+            snapshots
+                .last()
+                .map_or(0.0, |snapshot| snapshot.final_balance()),
+        ));
+    }
+
+    let yearly_balances: Vec<YearPercentiles> = yearly_balances
+        .into_iter()
+        .enumerate()
+        .map(|(year, balances)| YearPercentiles {
+            year,
+            balance: Percentiles::from_values(balances),
+        })
+        .collect();
+    let final_balance = match yearly_balances.last() {
+        Some(last_year) => last_year.balance,
+        None => return Err(error::SimulationError::InvalidInvestmentResults),
+    };
+
+    Ok(MonteCarloResult {
+        simulations,
+        seed,
+        final_balance,
+        yearly_balances,
+        success: SuccessResult::from_paths(&paths, config.years),
+    })
+}
+
+/// Linearly interpolated percentile of an already sorted slice
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    if sorted.is_empty() {
+        return f64::NAN;
+    }
+    let rank = p * (sorted.len() - 1) as f64;
+    let lower = rank.floor() as usize;
+    let upper = rank.ceil() as usize;
+    let weight = rank - lower as f64;
+
+    sorted[lower] + (sorted[upper] - sorted[lower]) * weight
+}
+
+#[cfg(test)]
+mod test {
+    use super::{percentile, run_monte_carlo, Percentiles, SuccessResult, YearShare};
+    use crate::distributions::{CsvRepository, DEFAULT_DIRECTORY};
+    use crate::investment_config::Configuration;
+    use assert_float_eq::assert_f64_near;
+
+    #[test]
+    fn test_percentile() {
+        let values = vec![1.0, 2.0, 3.0, 4.0, 5.0];
+        assert_f64_near!(percentile(&values, 0.0), 1.0);
+        assert_f64_near!(percentile(&values, 0.5), 3.0);
+        assert_f64_near!(percentile(&values, 0.25), 2.0);
+        assert_f64_near!(percentile(&values, 0.95), 4.8);
+        assert_f64_near!(percentile(&values, 1.0), 5.0);
+    }
+
+    #[test]
+    fn test_monte_carlo_with_fixed_rates() {
+        let config: Configuration = serde_json::from_value(serde_json::json!({
+            "deposit": 10000,
+            "years": 3,
+            "return_rates": 0.05,
+            "annual_contributions": 0.0,
+        }))
+        .unwrap();
+        let repository = CsvRepository::load(DEFAULT_DIRECTORY).unwrap();
+        let result = run_monte_carlo(&config, 10, 0, &repository).unwrap();
+
+        assert_eq!(result.yearly_balances.len(), 3);
+        assert_f64_near!(result.final_balance.p5, 11576.25);
+        assert_f64_near!(result.final_balance.p95, 11576.25);
+    }
+
+    #[test]
+    fn test_monte_carlo_with_distribution_is_ordered() {
+        let config: Configuration = serde_json::from_value(serde_json::json!({
+            "deposit": 10000,
+            "years": 10,
+            "return_rates": "sp500",
+            "annual_contributions": 1000.0,
+        }))
+        .unwrap();
+        let repository = CsvRepository::load(DEFAULT_DIRECTORY).unwrap();
+        let result = run_monte_carlo(&config, 200, 0, &repository).unwrap();
+        let Percentiles {
+            p5,
+            p25,
+            p50,
+            p75,
+            p95,
+        } = result.final_balance;
+
+        assert!(p5 <= p25 && p25 <= p50 && p50 <= p75 && p75 <= p95);
+
+        let same_seed = run_monte_carlo(&config, 200, 0, &repository).unwrap();
+        assert_eq!(same_seed.final_balance, result.final_balance);
+    }
+
+    #[test]
+    fn test_success_result() {
+        let paths = vec![
+            (None, 1000.0),
+            (Some(1), 0.0),
+            (None, 3000.0),
+            (Some(2), 0.0),
+            (None, 2000.0),
+        ];
+        let success = SuccessResult::from_paths(&paths, 3);
+
+        assert_f64_near!(success.success_rate(), 0.6);
+        assert_f64_near!(success.ruin_rate, 0.4);
+        assert_eq!(success.median_successful_final_balance, Some(2000.0));
+        assert_eq!(
+            success.survival_curve,
+            vec![
+                YearShare {
+                    year: 0,
+                    share: 1.0
+                },
+                YearShare {
+                    year: 1,
+                    share: 0.8
+                },
+                YearShare {
+                    year: 2,
+                    share: 0.6
+                },
+            ]
+        );
+        assert_f64_near!(success.depletion_years[1].share, 0.2);
+        assert_f64_near!(success.depletion_year.unwrap().p50, 1.5);
+    }
+
+    #[test]
+    fn test_monte_carlo_reports_ruin() {
+        let config: Configuration = serde_json::from_value(serde_json::json!({
+            "deposit": 10000,
+            "years": 5,
+            "return_rates": 0.0,
+            "annual_contributions": 0.0,
+            "withdrawals": {"strategy": {"type": "fixed", "amount": 4000.0}},
+        }))
+        .unwrap();
+        let repository = CsvRepository::load(DEFAULT_DIRECTORY).unwrap();
+        let result = run_monte_carlo(&config, 10, 0, &repository).unwrap();
+
+        // 10000 lasts for two full withdrawals, the third one empties the balance
+        assert_f64_near!(result.success.success_rate(), 0.0);
+        assert_f64_near!(result.success.depletion_years[2].share, 1.0);
+        assert_f64_near!(result.success.survival_curve[1].share, 1.0);
+        assert_f64_near!(result.success.survival_curve[2].share, 0.0);
+    }
+
+    #[test]
+    fn test_monte_carlo_without_simulations() {
+        let config: Configuration = serde_json::from_value(serde_json::json!({
+            "deposit": 10000,
+            "years": 3,
+            "return_rates": 0.05,
+            "annual_contributions": 0.0,
+        }))
+        .unwrap();
+        let repository = CsvRepository::load(DEFAULT_DIRECTORY).unwrap();
+        assert!(run_monte_carlo(&config, 0, 0, &repository).is_err());
+    }
+}
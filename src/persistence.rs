@@ -0,0 +1,114 @@
+use crate::error;
+use crate::investment;
+use crate::investment_config;
+
+pub const DEFAULT_PAGE_SIZE: i64 = 20;
+pub const MAX_PAGE_SIZE: i64 = 100;
+
+#[derive(serde::Serialize, Debug)]
+pub struct SimulationRecord {
+    id: uuid::Uuid,
+    created_at: chrono::DateTime<chrono::Utc>,
+    configuration: serde_json::Value,
+    seed: u64,
+    snapshots: serde_json::Value,
+    result: serde_json::Value,
+}
+
+#[derive(serde::Serialize, Debug)]
+pub struct SimulationSummary {
+    id: uuid::Uuid,
+    created_at: chrono::DateTime<chrono::Utc>,
+    seed: u64,
+    result: serde_json::Value,
+}
+
+#[derive(serde::Serialize, Debug)]
+pub struct SimulationPage {
+    page: i64,
+    page_size: i64,
+    total: i64,
+    simulations: Vec<SimulationSummary>,
+}
+
+/// Stores the run under a new id. The seed is stored as a BIGINT, keeping its bits.
+pub async fn save_simulation(
+    pool: &sqlx::PgPool,
+    config: &investment_config::Configuration,
+    seed: u64,
+    simulation: &investment::Simulation,
+) -> Result<uuid::Uuid, error::ApplicationError> {
+    let id = uuid::Uuid::new_v4();
+    sqlx::query!(
+        "INSERT INTO simulations (id, configuration, seed, snapshots, result) VALUES ($1, $2, $3, $4, $5)",
+        id,
+        serde_json::to_value(config)?,
+        seed as i64,
+        serde_json::to_value(&simulation.snapshots)?,
+        serde_json::to_value(&simulation.result)?
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(id)
+}
+
+pub async fn get_simulation(
+    pool: &sqlx::PgPool,
+    id: uuid::Uuid,
+) -> Result<SimulationRecord, error::ApplicationError> {
+    let record = sqlx::query!(
+        "SELECT id, created_at, configuration, seed, snapshots, result FROM simulations WHERE id = $1",
+        id
+    )
+    .fetch_optional(pool)
+    .await?
+    .ok_or(error::ApplicationError::SimulationNotFound(id))?;
+
+    Ok(SimulationRecord {
+        id: record.id,
+        created_at: record.created_at,
+        configuration: record.configuration,
+        seed: record.seed as u64,
+        snapshots: record.snapshots,
+        result: record.result,
+    })
+}
+
+/// Most recent runs first, `page` starts at 1
+pub async fn list_simulations(
+    pool: &sqlx::PgPool,
+    page: i64,
+    page_size: i64,
+) -> Result<SimulationPage, error::ApplicationError> {
+    let page = page.max(1);
+    let page_size = page_size.clamp(1, MAX_PAGE_SIZE);
+
+    let total = sqlx::query!("SELECT count(id) FROM simulations")
+        .fetch_one(pool)
+        .await?
+        .count
+        .unwrap_or(0);
+    let simulations = sqlx::query!(
+        "SELECT id, created_at, seed, result FROM simulations ORDER BY created_at DESC, id LIMIT $1 OFFSET $2",
+        page_size,
+        (page - 1) * page_size
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|record| SimulationSummary {
+        id: record.id,
+        created_at: record.created_at,
+        seed: record.seed as u64,
+        result: record.result,
+    })
+    .collect();
+
+    Ok(SimulationPage {
+        page,
+        page_size,
+        total,
+        simulations,
+    })
+}
@@ -0,0 +1,323 @@
+use crate::distributions;
+use crate::error;
+use rand::Rng;
+use rand_distr::Distribution;
+
+/// Parametric models of the annual returns. `mean` and `volatility` are the arithmetic mean and
+/// standard deviation of the annual returns, while `drift` is the continuously compounded drift
+/// of the geometric Brownian motion. The Student-t needs more than 2 degrees of freedom to have
+/// a finite standard deviation. Normal and Student-t draws below -100% are floored at -100%, a
+/// total loss.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(tag = "model", rename_all = "snake_case")]
+pub enum ReturnModel {
+    Normal {
+        mean: f64,
+        volatility: f64,
+    },
+    LogNormal {
+        mean: f64,
+        volatility: f64,
+    },
+    StudentT {
+        mean: f64,
+        volatility: f64,
+        degrees_of_freedom: f64,
+    },
+    GeometricBrownianMotion {
+        drift: f64,
+        volatility: f64,
+    },
+}
+
+#[derive(serde::Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ModelKind {
+    Normal,
+    LogNormal,
+    StudentT,
+    GeometricBrownianMotion,
+}
+
+/// Degrees of freedom used when the data doesn't show fat tails
+const MAX_DEGREES_OF_FREEDOM: f64 = 30.0;
+
+impl ReturnModel {
+    pub fn sample<R: Rng + ?Sized>(
+        &self,
+        years: usize,
+        rng: &mut R,
+    ) -> Result<Vec<f64>, error::SimulationError> {
+        let invalid = |_| error::SimulationError::InvalidReturnModel(*self);
+        if self.volatility().is_nan() || self.volatility() < 0.0 {
+            return Err(error::SimulationError::InvalidReturnModel(*self));
+        }
+        match *self {
+            ReturnModel::Normal { mean, volatility } => {
+                let normal = rand_distr::Normal::new(mean, volatility).map_err(invalid)?;
+                Ok(normal
+                    .sample_iter(rng)
+                    .take(years)
+                    .map(|value: f64| value.max(-1.0))
+                    .collect())
+            }
+            ReturnModel::LogNormal { mean, volatility } => {
+                // Parameters of the logarithm of the gross return 1 + r
+                let variance = (1.0 + (volatility / (1.0 + mean)).powi(2)).ln();
+                let mu = (1.0 + mean).ln() - variance / 2.0;
+                let log_normal =
+                    rand_distr::LogNormal::new(mu, variance.sqrt()).map_err(invalid)?;
+                Ok(log_normal
+                    .sample_iter(rng)
+                    .take(years)
+                    .map(|gross_return| gross_return - 1.0)
+                    .collect())
+            }
+            ReturnModel::StudentT {
+                mean,
+                volatility,
+                degrees_of_freedom,
+            } => {
+                // With 2 degrees of freedom or fewer the variance is infinite
+                if degrees_of_freedom.is_nan() || degrees_of_freedom <= 2.0 {
+                    return Err(error::SimulationError::InvalidReturnModel(*self));
+                }
+                let student_t = rand_distr::StudentT::new(degrees_of_freedom)
+                    .map_err(|_| error::SimulationError::InvalidReturnModel(*self))?;
+                // Scale the distribution so its standard deviation is `volatility`
+                let scale = volatility * ((degrees_of_freedom - 2.0) / degrees_of_freedom).sqrt();
+                Ok(student_t
+                    .sample_iter(rng)
+                    .take(years)
+                    .map(|value| (mean + scale * value).max(-1.0))
+                    .collect())
+            }
+            ReturnModel::GeometricBrownianMotion { drift, volatility } => {
+                let normal = rand_distr::Normal::new(drift - volatility.powi(2) / 2.0, volatility)
+                    .map_err(invalid)?;
+                Ok(normal
+                    .sample_iter(rng)
+                    .take(years)
+                    .map(|log_return: f64| log_return.exp() - 1.0)
+                    .collect())
+            }
+        }
+    }
+
+    fn volatility(&self) -> f64 {
+        match *self {
+            ReturnModel::Normal { volatility, .. }
+            | ReturnModel::LogNormal { volatility, .. }
+            | ReturnModel::StudentT { volatility, .. }
+            | ReturnModel::GeometricBrownianMotion { volatility, .. } => volatility,
+        }
+    }
+
+    /// Fits the parameters of the model to the annual returns using the method of moments
+    pub fn fit(kind: ModelKind, returns: &[f64]) -> Result<Self, error::SimulationError> {
+        if returns.len() < 2 {
+            return Err(error::SimulationError::NotEnoughData(returns.len()));
+        }
+        let (mean, volatility) = mean_and_deviation(returns);
+
+        let model = match kind {
+            ModelKind::Normal => ReturnModel::Normal { mean, volatility },
+            ModelKind::LogNormal => ReturnModel::LogNormal { mean, volatility },
+            ModelKind::StudentT => {
+                // The excess kurtosis of a Student-t is 6 / (v - 4)
+                let n = returns.len() as f64;
+                let fourth_moment = returns.iter().map(|r| (r - mean).powi(4)).sum::<f64>() / n;
+                let second_moment = volatility.powi(2) * (n - 1.0) / n;
+                let excess_kurtosis = fourth_moment / second_moment.powi(2) - 3.0;
+                let degrees_of_freedom = if excess_kurtosis > 0.0 {
+                    (6.0 / excess_kurtosis + 4.0).min(MAX_DEGREES_OF_FREEDOM)
+                } else {
+                    MAX_DEGREES_OF_FREEDOM
+                };
+                ReturnModel::StudentT {
+                    mean,
+                    volatility,
+                    degrees_of_freedom,
+                }
+            }
+            ModelKind::GeometricBrownianMotion => {
+                if returns.iter().any(|r| *r <= -1.0) {
+                    return Err(error::SimulationError::InvalidReturns);
+                }
+                let log_returns: Vec<f64> = returns.iter().map(|r| (1.0 + r).ln()).collect();
+                let (log_mean, log_volatility) = mean_and_deviation(&log_returns);
+                ReturnModel::GeometricBrownianMotion {
+                    drift: log_mean + log_volatility.powi(2) / 2.0,
+                    volatility: log_volatility,
+                }
+            }
+        };
+
+        Ok(model)
+    }
+}
+
+/// Fits the model to one of the historical distributions, e.g. `sp500`
+pub fn fit_distribution(
+    kind: ModelKind,
+    distribution_name: &str,
+    distributions: &dyn distributions::DistributionRepository,
+) -> Result<ReturnModel, error::SimulationError> {
+    let distribution = distributions.get(distribution_name)?;
+
+    ReturnModel::fit(kind, &distribution.values)
+}
+
+/// Arithmetic mean and sample standard deviation
+fn mean_and_deviation(values: &[f64]) -> (f64, f64) {
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1.0);
+
+    (mean, variance.sqrt())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{fit_distribution, mean_and_deviation, ModelKind, ReturnModel};
+    use crate::distributions::{CsvRepository, DEFAULT_DIRECTORY};
+    use rand::SeedableRng;
+
+    fn sample(model: ReturnModel) -> Vec<f64> {
+        model
+            .sample(20000, &mut rand::rngs::StdRng::seed_from_u64(11))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_models_match_their_moments() {
+        let models = [
+            ReturnModel::Normal {
+                mean: 0.07,
+                volatility: 0.15,
+            },
+            ReturnModel::LogNormal {
+                mean: 0.07,
+                volatility: 0.15,
+            },
+            ReturnModel::StudentT {
+                mean: 0.07,
+                volatility: 0.15,
+                degrees_of_freedom: 5.0,
+            },
+        ];
+        for model in models {
+            let (mean, volatility) = mean_and_deviation(&sample(model));
+            assert!((mean - 0.07).abs() < 0.01, "{:?} mean {}", model, mean);
+            assert!(
+                (volatility - 0.15).abs() < 0.015,
+                "{:?} volatility {}",
+                model,
+                volatility
+            );
+        }
+    }
+
+    #[test]
+    fn test_geometric_brownian_motion_never_loses_everything() {
+        let returns = sample(ReturnModel::GeometricBrownianMotion {
+            drift: 0.07,
+            volatility: 0.3,
+        });
+        assert!(returns.iter().all(|r| *r > -1.0));
+
+        // The expected gross return of a GBM over a year is exp(drift)
+        let (mean, _) = mean_and_deviation(&returns);
+        assert!((mean - (0.07f64.exp() - 1.0)).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_returns_never_fall_below_a_total_loss() {
+        let models = [
+            ReturnModel::Normal {
+                mean: 0.07,
+                volatility: 0.45,
+            },
+            ReturnModel::StudentT {
+                mean: 0.07,
+                volatility: 0.45,
+                degrees_of_freedom: 2.5,
+            },
+        ];
+        for model in models {
+            assert!(sample(model).iter().all(|r| *r >= -1.0), "{:?}", model);
+        }
+    }
+
+    #[test]
+    fn test_invalid_model_parameters() {
+        let model = ReturnModel::Normal {
+            mean: 0.07,
+            volatility: -0.15,
+        };
+        assert!(model
+            .sample(3, &mut rand::rngs::StdRng::seed_from_u64(0))
+            .is_err());
+
+        let model = ReturnModel::StudentT {
+            mean: 0.07,
+            volatility: 0.15,
+            degrees_of_freedom: 1.5,
+        };
+        assert!(model
+            .sample(3, &mut rand::rngs::StdRng::seed_from_u64(0))
+            .is_err());
+    }
+
+    #[test]
+    fn test_fit_recovers_the_parameters() {
+        let returns = sample(ReturnModel::GeometricBrownianMotion {
+            drift: 0.07,
+            volatility: 0.2,
+        });
+        match ReturnModel::fit(ModelKind::GeometricBrownianMotion, &returns).unwrap() {
+            ReturnModel::GeometricBrownianMotion { drift, volatility } => {
+                assert!((drift - 0.07).abs() < 0.01);
+                assert!((volatility - 0.2).abs() < 0.01);
+            }
+            model => panic!("Unexpected model {:?}", model),
+        }
+    }
+
+    #[test]
+    fn test_fit_distribution() {
+        let repository = CsvRepository::load(DEFAULT_DIRECTORY).unwrap();
+        let model = fit_distribution(ModelKind::StudentT, "sp500", &repository).unwrap();
+        match model {
+            ReturnModel::StudentT {
+                mean,
+                volatility,
+                degrees_of_freedom,
+            } => {
+                assert!(mean > 0.0 && volatility > 0.0);
+                assert!(degrees_of_freedom > 4.0);
+            }
+            model => panic!("Unexpected model {:?}", model),
+        }
+    }
+
+    #[test]
+    fn test_model_deserialization() {
+        let model: ReturnModel = serde_json::from_value(serde_json::json!({
+            "model": "student_t",
+            "mean": 0.07,
+            "volatility": 0.15,
+            "degrees_of_freedom": 5.0,
+        }))
+        .unwrap();
+
+        assert_eq!(
+            model,
+            ReturnModel::StudentT {
+                mean: 0.07,
+                volatility: 0.15,
+                degrees_of_freedom: 5.0
+            }
+        );
+    }
+}
@@ -0,0 +1,263 @@
+use crate::distributions;
+use crate::error;
+use crate::investment;
+use crate::investment_config;
+use crate::validation;
+use rand::SeedableRng;
+
+/// Amount every input is moved up and down around the base case
+#[derive(serde::Serialize, serde::Deserialize, clap::Args, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct Variations {
+    #[arg(long, default_value_t = 0.1, help = "Relative change of the deposit")]
+    pub deposit: f64,
+    #[arg(
+        long,
+        default_value_t = 0.1,
+        help = "Relative change of the contributions"
+    )]
+    pub contributions: f64,
+    #[arg(
+        long,
+        default_value_t = 0.01,
+        help = "Change of the yearly return rate"
+    )]
+    pub return_rate: f64,
+    #[arg(long, default_value_t = 0.0025, help = "Change of the expense ratio")]
+    pub fees: f64,
+    #[arg(long, default_value_t = 2, help = "Change of the number of years")]
+    pub years: usize,
+}
+
+impl Default for Variations {
+    fn default() -> Self {
+        Variations {
+            deposit: 0.1,
+            contributions: 0.1,
+            return_rate: 0.01,
+            fees: 0.0025,
+            years: 2,
+        }
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+pub struct SensitivityRequest {
+    #[serde(flatten)]
+    pub config: investment_config::Configuration,
+    #[serde(default)]
+    pub variations: Variations,
+}
+
+#[derive(serde::Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Input {
+    Deposit,
+    Contributions,
+    ReturnRate,
+    Fees,
+    Years,
+}
+
+#[derive(serde::Serialize, Debug)]
+pub struct InputSensitivity {
+    input: Input,
+    variation: f64,
+    low_final_balance: f64,
+    high_final_balance: f64,
+    /// Distance between the final balances of both cases
+    swing: f64,
+}
+
+#[derive(serde::Serialize, Debug)]
+pub struct SensitivityResult {
+    seed: u64,
+    base_final_balance: f64,
+    /// Sorted from the input moving the final balance the most. The years are left out when the
+    /// rates or contributions don't cover the longer plan.
+    inputs: Vec<InputSensitivity>,
+}
+
+/// Simulates the configuration with every input moved down and up by its variation, keeping the
+/// rest of the inputs and the seed of the base case
+pub fn sensitivity_analysis(
+    config: &investment_config::Configuration,
+    variations: Variations,
+    seed: u64,
+    distributions: &dyn distributions::DistributionRepository,
+) -> Result<SensitivityResult, error::SimulationError> {
+    let final_balance = |config: &investment_config::Configuration,
+                         shift: f64|
+     -> Result<f64, error::SimulationError> {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+        let investment = config
+            .investment(distributions, &mut rng)?
+            .with_shifted_returns(shift);
+        let snapshots = investment::annual_results(&investment.simulate()?);
+        Ok(investment::get_investment_result(&snapshots, config.risk_free_rate)?.final_balance())
+    };
+    let base_final_balance = final_balance(config, 0.0)?;
+
+    let mut inputs = Vec::new();
+    for input in [
+        Input::Deposit,
+        Input::Contributions,
+        Input::ReturnRate,
+        Input::Fees,
+        Input::Years,
+    ] {
+        let (variation, low, high) = match input {
+            Input::ReturnRate => (
+                variations.return_rate,
+                final_balance(config, -variations.return_rate)?,
+                final_balance(config, variations.return_rate)?,
+            ),
+            _ => {
+                let variation = match input {
+                    Input::Deposit => variations.deposit,
+                    Input::Contributions => variations.contributions,
+                    Input::Fees => variations.fees,
+                    _ => variations.years as f64,
+                };
+                let balances =
+                    final_balance(&varied(config, input, -variation), 0.0).and_then(|low| {
+                        Ok((low, final_balance(&varied(config, input, variation), 0.0)?))
+                    });
+                match balances {
+                    Ok((low, high)) => (variation, low, high),
+                    Err(
+                        error::SimulationError::NotEnoughValues(..)
+                        | error::SimulationError::NotEnoughHistory(_),
+                    ) if input == Input::Years => continue,
+                    Err(error) => return Err(error),
+                }
+            }
+        };
+        inputs.push(InputSensitivity {
+            input,
+            variation,
+            low_final_balance: low,
+            high_final_balance: high,
+            swing: (high - low).abs(),
+        });
+    }
+    inputs.sort_by(|a, b| b.swing.total_cmp(&a.swing));
+
+    Ok(SensitivityResult {
+        seed,
+        base_final_balance,
+        inputs,
+    })
+}
+
+/// Configuration with `input` moved by `change`, the amounts never go below zero and the years
+/// stay between one and the longest plan
+fn varied(
+    config: &investment_config::Configuration,
+    input: Input,
+    change: f64,
+) -> investment_config::Configuration {
+    let mut config = config.clone();
+    match input {
+        Input::Deposit => {
+            config.deposit = (config.deposit as f64 * (1.0 + change)).max(0.0).round() as usize
+        }
+        Input::Contributions => {
+            config.annual_contributions =
+                config.annual_contributions.scaled((1.0 + change).max(0.0))
+        }
+        Input::Fees => config.fees.expense_ratio = (config.fees.expense_ratio + change).max(0.0),
+        Input::Years => {
+            config.years =
+                (config.years as f64 + change).clamp(1.0, validation::MAX_YEARS as f64) as usize;
+        }
+        Input::ReturnRate => {}
+    }
+    config
+}
+
+impl std::fmt::Display for SensitivityResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Base final balance: {:.2}", self.base_final_balance)?;
+        writeln!(
+            f,
+            "{:<15}{:>12}{:>20}{:>20}{:>16}",
+            "Input", "Variation", "Low final balance", "High final balance", "Swing"
+        )?;
+        for input in self.inputs.iter() {
+            let name = serde_json::to_value(input.input)
+                .ok()
+                .and_then(|value| value.as_str().map(str::to_string))
+                .unwrap_or_default();
+            writeln!(
+                f,
+                "{:<15}{:>12}{:>20.2}{:>20.2}{:>16.2}",
+                name,
+                format!("±{}", input.variation),
+                input.low_final_balance,
+                input.high_final_balance,
+                input.swing
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{sensitivity_analysis, Input, Variations};
+    use crate::distributions::{CsvRepository, DEFAULT_DIRECTORY};
+    use crate::investment_config::Configuration;
+    use assert_float_eq::assert_f64_near;
+
+    #[test]
+    fn test_sensitivity_analysis() {
+        let config: Configuration = serde_json::from_value(serde_json::json!({
+            "deposit": 10000,
+            "years": 2,
+            "return_rates": 0.1,
+            "annual_contributions": 1000.0,
+        }))
+        .unwrap();
+        let repository = CsvRepository::load(DEFAULT_DIRECTORY).unwrap();
+        let variations = Variations {
+            years: 1,
+            ..Variations::default()
+        };
+        let result = sensitivity_analysis(&config, variations, 0, &repository).unwrap();
+
+        assert_f64_near!(result.base_final_balance, 14410.0);
+        // The deposit moves by 1000 and is invested for two years
+        let deposit = result
+            .inputs
+            .iter()
+            .find(|sensitivity| sensitivity.input == Input::Deposit)
+            .unwrap();
+        assert_f64_near!(deposit.low_final_balance, 14410.0 - 1210.0, 10);
+        assert_f64_near!(deposit.high_final_balance, 14410.0 + 1210.0, 10);
+        assert!(result
+            .inputs
+            .windows(2)
+            .all(|pair| pair[0].swing >= pair[1].swing));
+        assert_eq!(result.inputs[0].input, Input::Years);
+    }
+
+    #[test]
+    fn test_years_are_skipped_without_enough_rates() {
+        let config: Configuration = serde_json::from_value(serde_json::json!({
+            "deposit": 10000,
+            "years": 2,
+            "return_rates": [0.1, 0.05],
+            "annual_contributions": 1000.0,
+        }))
+        .unwrap();
+        let repository = CsvRepository::load(DEFAULT_DIRECTORY).unwrap();
+        let result = sensitivity_analysis(&config, Variations::default(), 0, &repository).unwrap();
+
+        assert_eq!(result.inputs.len(), 4);
+        assert!(result
+            .inputs
+            .iter()
+            .all(|sensitivity| sensitivity.input != Input::Years));
+    }
+}
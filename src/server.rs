@@ -1,36 +1,98 @@
 use axum::extract;
+use axum::http::header;
+use axum::http::HeaderMap;
 use axum::http::StatusCode;
 use axum::response;
 use axum::response::IntoResponse;
 use axum::routing;
 use axum::Router;
+use std::sync::Arc;
+use tokio::sync::RwLock;
 
+use crate::backtest;
+use crate::compare;
 use crate::distributions;
+use crate::distributions::DistributionRepository;
 use crate::error;
 use crate::investment;
 use crate::investment_config;
-use crate::types;
+use crate::monte_carlo;
+use crate::persistence;
+use crate::sensitivity;
+use crate::solver;
+use crate::statistics;
+use crate::sweep;
+use crate::validation;
 
 pub struct Server<'a> {
     host: String,
     port: String,
     pg_pool: &'a sqlx::PgPool,
+    distributions_dir: String,
+}
+
+/// Shared by every handler, the distributions are loaded once at startup
+#[derive(Clone)]
+struct AppState {
+    pg_pool: sqlx::PgPool,
+    distributions: Arc<RwLock<distributions::PostgresRepository>>,
 }
 
 impl<'a> Server<'a> {
-    pub fn new(host: String, port: String, pg_pool: &'a sqlx::PgPool) -> Self {
+    pub fn new(
+        host: String,
+        port: String,
+        pg_pool: &'a sqlx::PgPool,
+        distributions_dir: String,
+    ) -> Self {
         Self {
             host,
             port,
             pg_pool,
+            distributions_dir,
         }
     }
 
     pub async fn serve(&self) -> Result<(), error::ApplicationError> {
         self.setup_db().await?;
+        let state = AppState {
+            pg_pool: self.pg_pool.clone(),
+            distributions: Arc::new(RwLock::new(
+                distributions::PostgresRepository::load(
+                    self.pg_pool,
+                    distributions::CsvRepository::load(&self.distributions_dir)?
+                        .inflation_series()
+                        .clone(),
+                )
+                .await?,
+            )),
+        };
         let app = Router::new()
             .route("/check", routing::get(health_check))
-            .route("/simulate", routing::post(get_investment_result));
+            .route("/simulate", routing::post(get_investment_result))
+            .route("/monte-carlo", routing::post(get_monte_carlo_result))
+            .route("/rolling", routing::post(get_rolling_analysis_result))
+            .route("/solve", routing::post(solve_goal))
+            .route("/sensitivity", routing::post(get_sensitivity_analysis))
+            .route("/sweep", routing::post(run_sweep))
+            .route("/compare", routing::post(compare_scenarios))
+            .route("/simulations", routing::get(list_simulations))
+            .route("/simulations/:id", routing::get(get_simulation))
+            .route(
+                "/distributions",
+                routing::get(list_distributions).post(create_distribution),
+            )
+            .route(
+                "/distributions/:name",
+                routing::get(get_distribution)
+                    .put(replace_distribution)
+                    .delete(delete_distribution),
+            )
+            .route(
+                "/distributions/:name/stats",
+                routing::get(get_distribution_statistics),
+            )
+            .with_state(state);
         let listener = tokio::net::TcpListener::bind(format!("{}:{}", self.host, self.port))
             .await
             .unwrap();
@@ -49,11 +111,13 @@ impl<'a> Server<'a> {
 
         println!("The count is {}", count);
         if count == 0 {
-            for (name, data) in distributions::get_distributions().iter() {
+            let repository = distributions::CsvRepository::load(&self.distributions_dir)?;
+            for (name, data) in repository.distributions().iter() {
                 sqlx::query!(
-                    "INSERT INTO real_distributions (name, data) VALUES ($1, $2)",
+                    "INSERT INTO real_distributions (name, years, data) VALUES ($1, $2, $3)",
                     name,
-                    data
+                    &data.years,
+                    &data.values
                 )
                 .execute(self.pg_pool)
                 .await?;
@@ -63,29 +127,508 @@ impl<'a> Server<'a> {
     }
 }
 
-async fn get_investment_result(
-    extract::Json(config): extract::Json<investment_config::Configuration>,
-) -> response::Json<investment::InvestmentResult> {
-    let investment = investment::Investment::new(
-        types::PositiveFloat::try_from(config.deposit as f64).unwrap(),
-        config.years,
-        config
-            .annual_contributions
-            .to_annual_contributions(config.years),
-        config.return_rates.to_interest_rates(config.years),
-    );
-
-    let investment_snapshots = investment.simulate().unwrap();
-    let investment_results: Vec<investment::InvestmentSnapshotResult> = investment_snapshots
+/// JSON body whose type errors are reported like the invalid fields, with the path of the field
+/// that couldn't be read
+struct ValidJson<T>(T);
+
+#[axum::async_trait]
+impl<T, S> extract::FromRequest<S> for ValidJson<T>
+where
+    T: serde::de::DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = error::SimulationError;
+
+    async fn from_request(request: extract::Request, state: &S) -> Result<Self, Self::Rejection> {
+        let invalid = |field: error::FieldError| error::SimulationError::Validation(vec![field]);
+        let body = axum::body::Bytes::from_request(request, state)
+            .await
+            .map_err(|rejection| invalid(error::FieldError::new("body", rejection.body_text())))?;
+        let value: serde_json::Value = serde_json::from_slice(&body)
+            .map_err(|error| invalid(error::FieldError::new("body", error.to_string())))?;
+        let request = serde_path_to_error::deserialize(&value)
+            .map_err(|error| invalid(type_error(&value, error)))?;
+
+        Ok(ValidJson(request))
+    }
+}
+
+/// The fields of a flattened configuration lose their path, so the object holding it is read
+/// again as a configuration to find the field
+fn type_error(
+    value: &serde_json::Value,
+    error: serde_path_to_error::Error<serde_json::Error>,
+) -> error::FieldError {
+    let join = |path: String, field: String| match (path.as_str(), field.as_str()) {
+        (".", ".") => "body".to_string(),
+        (".", _) => field,
+        (_, ".") => path,
+        _ => format!("{}.{}", path, field),
+    };
+    let object = error
+        .path()
         .iter()
-        .map(|snapshot| snapshot.result())
+        .try_fold(value, |value, segment| match segment {
+            serde_path_to_error::Segment::Seq { index } => value.get(index),
+            serde_path_to_error::Segment::Map { key } => value.get(key),
+            _ => None,
+        });
+    if let Some(object) = object.filter(|object| object.is_object()) {
+        if let Err(config_error) =
+            serde_path_to_error::deserialize::<_, investment_config::Configuration>(object)
+        {
+            let field = join(error.path().to_string(), config_error.path().to_string());
+            return error::FieldError::new(&field, config_error.into_inner().to_string());
+        }
+    }
+    let field = join(error.path().to_string(), ".".to_string());
+    error::FieldError::new(&field, error.into_inner().to_string())
+}
+
+#[derive(serde::Serialize)]
+struct SimulationResponse {
+    id: uuid::Uuid,
+    #[serde(flatten)]
+    result: investment::InvestmentResult,
+}
+
+async fn get_investment_result(
+    extract::State(state): extract::State<AppState>,
+    ValidJson(config): ValidJson<investment_config::Configuration>,
+) -> Result<response::Json<SimulationResponse>, error::ApplicationError> {
+    let seed = config.seed();
+    let simulation = {
+        let distributions = state.distributions.read().await;
+        validation::validate_configuration(&config, &*distributions)?;
+        config.simulate(seed, &*distributions)?
+    };
+    let id = persistence::save_simulation(&state.pg_pool, &config, seed, &simulation).await?;
+
+    Ok(response::Json(SimulationResponse {
+        id,
+        result: simulation.result,
+    }))
+}
+
+#[derive(serde::Deserialize)]
+struct Pagination {
+    page: Option<i64>,
+    page_size: Option<i64>,
+}
+
+async fn list_simulations(
+    extract::State(state): extract::State<AppState>,
+    extract::Query(pagination): extract::Query<Pagination>,
+) -> Result<response::Json<persistence::SimulationPage>, error::ApplicationError> {
+    let page = persistence::list_simulations(
+        &state.pg_pool,
+        pagination.page.unwrap_or(1),
+        pagination
+            .page_size
+            .unwrap_or(persistence::DEFAULT_PAGE_SIZE),
+    )
+    .await?;
+
+    Ok(response::Json(page))
+}
+
+async fn get_simulation(
+    extract::State(state): extract::State<AppState>,
+    extract::Path(id): extract::Path<uuid::Uuid>,
+) -> Result<response::Json<persistence::SimulationRecord>, error::ApplicationError> {
+    let simulation = persistence::get_simulation(&state.pg_pool, id).await?;
+
+    Ok(response::Json(simulation))
+}
+
+/// Runs CPU heavy work on the blocking threads with a copy of the distributions, so neither the
+/// runtime nor the writes of the distributions wait for it
+async fn run_blocking<T, F>(state: &AppState, work: F) -> Result<T, error::SimulationError>
+where
+    T: Send + 'static,
+    F: FnOnce(&distributions::InMemoryRepository) -> Result<T, error::SimulationError>
+        + Send
+        + 'static,
+{
+    let distributions = {
+        let distributions = state.distributions.read().await;
+        distributions::InMemoryRepository::new(distributions.distributions().clone())
+            .with_inflation_series(distributions.inflation_series().clone())
+    };
+    tokio::task::spawn_blocking(move || work(&distributions))
+        .await
+        .map_err(|_| error::SimulationError::InvalidInvestmentResults)?
+}
+
+async fn get_monte_carlo_result(
+    extract::State(state): extract::State<AppState>,
+    ValidJson(config): ValidJson<investment_config::Configuration>,
+) -> Result<response::Json<monte_carlo::MonteCarloResult>, error::SimulationError> {
+    let monte_carlo_result = run_blocking(&state, move |distributions| {
+        validation::validate_configuration(&config, distributions)?;
+        let simulations = config
+            .simulations
+            .unwrap_or(monte_carlo::DEFAULT_SIMULATIONS);
+        monte_carlo::run_monte_carlo(&config, simulations, config.seed(), distributions)
+    })
+    .await?;
+
+    Ok(response::Json(monte_carlo_result))
+}
+
+async fn get_rolling_analysis_result(
+    extract::State(state): extract::State<AppState>,
+    ValidJson(config): ValidJson<investment_config::Configuration>,
+) -> Result<response::Json<backtest::RollingAnalysisResult>, error::SimulationError> {
+    let rolling_result = run_blocking(&state, move |distributions| {
+        validation::validate_configuration(&config, distributions)?;
+        backtest::rolling_analysis(&config, config.seed(), distributions)
+    })
+    .await?;
+
+    Ok(response::Json(rolling_result))
+}
+
+async fn solve_goal(
+    extract::State(state): extract::State<AppState>,
+    ValidJson(request): ValidJson<solver::SolveRequest>,
+) -> Result<response::Json<solver::SolveResult>, error::SimulationError> {
+    let solve_result = run_blocking(&state, move |distributions| {
+        validation::validate_configuration(&request.config, distributions)?;
+        solver::solve(
+            &request.config,
+            request.goal,
+            request.config.seed(),
+            distributions,
+        )
+    })
+    .await?;
+
+    Ok(response::Json(solve_result))
+}
+
+async fn get_sensitivity_analysis(
+    extract::State(state): extract::State<AppState>,
+    ValidJson(request): ValidJson<sensitivity::SensitivityRequest>,
+) -> Result<response::Json<sensitivity::SensitivityResult>, error::SimulationError> {
+    let sensitivity_result = run_blocking(&state, move |distributions| {
+        validation::validate_configuration(&request.config, distributions)?;
+        sensitivity::sensitivity_analysis(
+            &request.config,
+            request.variations,
+            request.config.seed(),
+            distributions,
+        )
+    })
+    .await?;
+
+    Ok(response::Json(sensitivity_result))
+}
+
+async fn compare_scenarios(
+    extract::State(state): extract::State<AppState>,
+    ValidJson(request): ValidJson<compare::ComparisonRequest>,
+) -> Result<response::Json<compare::ComparisonResult>, error::SimulationError> {
+    let seed = request.seed.unwrap_or_else(|| {
+        request
+            .scenarios
+            .first()
+            .map_or_else(rand::random, |scenario| scenario.config.seed())
+    });
+    let comparison_result = run_blocking(&state, move |distributions| {
+        compare::compare(&request.scenarios, seed, distributions)
+    })
+    .await?;
+
+    Ok(response::Json(comparison_result))
+}
+
+#[derive(serde::Deserialize)]
+struct SweepQuery {
+    format: Option<sweep::Format>,
+}
+
+async fn run_sweep(
+    extract::State(state): extract::State<AppState>,
+    extract::Query(query): extract::Query<SweepQuery>,
+    ValidJson(request): ValidJson<sweep::SweepRequest>,
+) -> Result<response::Response, error::ApplicationError> {
+    let sweep_result = run_blocking(&state, move |distributions| {
+        validation::validate_configuration(&request.config, distributions)?;
+        sweep::run_sweep(
+            &request.config,
+            &request.sweep,
+            request.config.seed(),
+            distributions,
+        )
+    })
+    .await?;
+
+    Ok(match query.format.unwrap_or(sweep::Format::Json) {
+        sweep::Format::Json => response::Json(sweep_result).into_response(),
+        sweep::Format::Csv => {
+            ([(header::CONTENT_TYPE, "text/csv")], sweep_result.to_csv()?).into_response()
+        }
+    })
+}
+
+#[derive(serde::Serialize)]
+struct DistributionSummary {
+    name: String,
+    start_year: Option<i32>,
+    end_year: Option<i32>,
+    years: usize,
+}
+
+/// Returns are always served as fractions
+#[derive(serde::Serialize)]
+struct DistributionResponse {
+    name: String,
+    unit: distributions::Unit,
+    returns: Vec<distributions::YearlyReturn>,
+}
+
+impl DistributionResponse {
+    fn new(name: &str, distribution: &distributions::Distribution) -> Self {
+        Self {
+            name: name.to_string(),
+            unit: distributions::Unit::Fraction,
+            returns: distribution
+                .years
+                .iter()
+                .zip(distribution.values.iter())
+                .map(|(&year, &value)| distributions::YearlyReturn { year, value })
+                .collect(),
+        }
+    }
+}
+
+/// CSV uploads take the name and unit from the query, e.g. `?name=bonds&unit=percent`
+#[derive(serde::Deserialize)]
+struct UploadParams {
+    name: Option<String>,
+    unit: Option<distributions::Unit>,
+}
+
+#[derive(serde::Deserialize)]
+struct JsonUpload {
+    name: Option<String>,
+    #[serde(flatten)]
+    upload: distributions::DistributionUpload,
+}
+
+/// Reads a JSON or, with a `text/csv` content type, a CSV upload
+fn parse_upload(
+    headers: &HeaderMap,
+    params: UploadParams,
+    body: &str,
+) -> Result<(Option<String>, distributions::DistributionUpload), error::ApplicationError> {
+    let is_csv = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .is_some_and(|content_type| content_type.starts_with("text/csv"));
+
+    if is_csv {
+        let unit = params.unit.ok_or_else(|| {
+            error::SimulationError::Validation(vec![error::FieldError::new(
+                "unit",
+                "is required, either `percent` or `fraction`",
+            )])
+        })?;
+        Ok((
+            params.name,
+            distributions::DistributionUpload::from_csv(unit, body)?,
+        ))
+    } else {
+        let upload: JsonUpload = serde_json::from_str(body)
+            .map_err(|e| error::ApplicationError::InvalidUpload(e.to_string()))?;
+        Ok((upload.name.or(params.name), upload.upload))
+    }
+}
+
+async fn list_distributions(
+    extract::State(state): extract::State<AppState>,
+) -> response::Json<Vec<DistributionSummary>> {
+    let distributions = state.distributions.read().await;
+    let summaries = distributions
+        .names()
+        .into_iter()
+        .map(|name| {
+            let distribution = &distributions.distributions()[name];
+            DistributionSummary {
+                name: name.to_string(),
+                start_year: distribution.years.iter().min().copied(),
+                end_year: distribution.years.iter().max().copied(),
+                years: distribution.years.len(),
+            }
+        })
         .collect();
 
-    let investment_result = investment::get_investment_result(investment_results).unwrap();
+    response::Json(summaries)
+}
+
+async fn get_distribution(
+    extract::State(state): extract::State<AppState>,
+    extract::Path(name): extract::Path<String>,
+) -> Result<response::Json<DistributionResponse>, error::ApplicationError> {
+    let distributions = state.distributions.read().await;
+    let distribution = distributions
+        .get(&name)
+        .map_err(|_| error::ApplicationError::DistributionNotFound(name.clone()))?;
 
-    response::Json(investment_result)
+    Ok(response::Json(DistributionResponse::new(
+        &name,
+        distribution,
+    )))
+}
+
+#[derive(serde::Deserialize)]
+struct HistogramParams {
+    bins: Option<usize>,
+}
+
+async fn get_distribution_statistics(
+    extract::State(state): extract::State<AppState>,
+    extract::Path(name): extract::Path<String>,
+    extract::Query(params): extract::Query<HistogramParams>,
+) -> Result<response::Json<statistics::DistributionStatistics>, error::ApplicationError> {
+    let distributions = state.distributions.read().await;
+    let distribution = distributions
+        .get(&name)
+        .map_err(|_| error::ApplicationError::DistributionNotFound(name.clone()))?;
+    let statistics = statistics::distribution_statistics(
+        &name,
+        distribution,
+        params.bins.unwrap_or(statistics::DEFAULT_HISTOGRAM_BINS),
+    )?;
+
+    Ok(response::Json(statistics))
+}
+
+async fn create_distribution(
+    extract::State(state): extract::State<AppState>,
+    extract::Query(params): extract::Query<UploadParams>,
+    headers: HeaderMap,
+    body: String,
+) -> Result<(StatusCode, response::Json<DistributionResponse>), error::ApplicationError> {
+    let (name, upload) = parse_upload(&headers, params, &body)?;
+    let name = name.unwrap_or_default();
+    distributions::validate_name(&name)?;
+    let distribution = upload.into_distribution()?;
+    let response = DistributionResponse::new(&name, &distribution);
+    state
+        .distributions
+        .write()
+        .await
+        .insert(&name, distribution)
+        .await?;
+
+    Ok((StatusCode::CREATED, response::Json(response)))
+}
+
+async fn replace_distribution(
+    extract::State(state): extract::State<AppState>,
+    extract::Path(name): extract::Path<String>,
+    extract::Query(params): extract::Query<UploadParams>,
+    headers: HeaderMap,
+    body: String,
+) -> Result<response::Json<DistributionResponse>, error::ApplicationError> {
+    let (_, upload) = parse_upload(&headers, params, &body)?;
+    let distribution = upload.into_distribution()?;
+    let response = DistributionResponse::new(&name, &distribution);
+    state
+        .distributions
+        .write()
+        .await
+        .update(&name, distribution)
+        .await?;
+
+    Ok(response::Json(response))
+}
+
+async fn delete_distribution(
+    extract::State(state): extract::State<AppState>,
+    extract::Path(name): extract::Path<String>,
+) -> Result<StatusCode, error::ApplicationError> {
+    state.distributions.write().await.delete(&name).await?;
+
+    Ok(StatusCode::NO_CONTENT)
 }
 
 async fn health_check() -> impl response::IntoResponse {
     StatusCode::OK.into_response()
 }
+
+#[cfg(test)]
+mod test {
+    use super::ValidJson;
+    use crate::compare::ComparisonRequest;
+    use crate::error::SimulationError;
+    use crate::investment_config::Configuration;
+    use crate::solver::SolveRequest;
+    use axum::extract::FromRequest;
+
+    async fn read_body<T: serde::de::DeserializeOwned>(body: &str) -> Result<T, SimulationError> {
+        let request = axum::http::Request::builder()
+            .method("POST")
+            .header("content-type", "application/json")
+            .body(axum::body::Body::from(body.to_string()))
+            .unwrap();
+        ValidJson::<T>::from_request(request, &())
+            .await
+            .map(|ValidJson(value)| value)
+    }
+
+    #[tokio::test]
+    async fn test_wrongly_typed_payloads_are_invalid_fields() {
+        let cases = [
+            (
+                r#"{"deposit": -1, "years": 3, "return_rates": 0.05, "annual_contributions": 0.0}"#,
+                "deposit",
+            ),
+            (
+                r#"{"deposit": 1000, "years": "3", "return_rates": 0.05, "annual_contributions": 0.0}"#,
+                "years",
+            ),
+            (
+                r#"{"deposit": 1000, "years": 3, "return_rates": 0.05, "annual_contributions": true}"#,
+                "annual_contributions",
+            ),
+            (r#"{"deposit": 1000, "years": 3"#, "body"),
+        ];
+        for (body, field) in cases {
+            match read_body::<Configuration>(body).await {
+                Err(SimulationError::Validation(errors)) => assert_eq!(errors[0].field, field),
+                _ => panic!("{} must be rejected", body),
+            }
+        }
+
+        let config = read_body::<Configuration>(
+            r#"{"deposit": 1000, "years": 3, "return_rates": 0.05, "annual_contributions": 0.0}"#,
+        )
+        .await
+        .unwrap();
+        assert_eq!(config.years, 3);
+    }
+
+    #[tokio::test]
+    async fn test_flattened_configurations_keep_the_field() {
+        let solve = read_body::<SolveRequest>(
+            r#"{"deposit": 1000, "years": "3", "return_rates": 0.05, "annual_contributions": 0.0, "solve_for": "years"}"#,
+        );
+        match solve.await {
+            Err(SimulationError::Validation(errors)) => assert_eq!(errors[0].field, "years"),
+            _ => panic!("The years must be rejected"),
+        }
+
+        let scenario = r#"{"name": "b", "deposit": 1000, "years": 3, "return_rates": 0.05, "annual_contributions": 0.0}"#;
+        let invalid = r#"{"name": "a", "deposit": 1000, "years": 3, "return_rates": 0.05, "annual_contributions": "x"}"#;
+        let body = format!(r#"{{"scenarios": [{}, {}]}}"#, scenario, invalid);
+        match read_body::<ComparisonRequest>(&body).await {
+            Err(SimulationError::Validation(errors)) => {
+                assert_eq!(errors[0].field, "scenarios[1].annual_contributions")
+            }
+            _ => panic!("The contributions must be rejected"),
+        }
+    }
+}
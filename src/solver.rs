@@ -0,0 +1,352 @@
+use crate::distributions;
+use crate::error;
+use crate::investment;
+use crate::investment_config;
+use crate::monte_carlo;
+use crate::types;
+use crate::validation;
+use rand::SeedableRng;
+
+const MAX_AMOUNT: f64 = 1e15;
+const MAX_RETURN_RATE: f64 = 10.0;
+const MIN_RETURN_RATE: f64 = -0.99;
+const MAX_ITERATIONS: usize = 200;
+
+#[derive(serde::Serialize, serde::Deserialize, clap::ValueEnum, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+pub enum Unknown {
+    /// Constant annual contribution
+    Contribution,
+    /// Constant return rate, replacing the configured returns and portfolio
+    ReturnRate,
+    Deposit,
+    Years,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy)]
+pub struct Goal {
+    pub solve_for: Unknown,
+    /// Share of the Monte Carlo paths that must reach the target. Without it a single path is
+    /// simulated.
+    pub probability: Option<f64>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+pub struct SolveRequest {
+    #[serde(flatten)]
+    pub config: investment_config::Configuration,
+    #[serde(flatten)]
+    pub goal: Goal,
+}
+
+#[derive(serde::Serialize, Debug)]
+pub struct SolveResult {
+    solve_for: Unknown,
+    value: f64,
+    target_balance: f64,
+    seed: u64,
+    /// Final balance of the single simulated path
+    #[serde(skip_serializing_if = "Option::is_none")]
+    final_balance: Option<f64>,
+    /// Share of the Monte Carlo paths reaching the target
+    #[serde(skip_serializing_if = "Option::is_none")]
+    success_probability: Option<f64>,
+}
+
+impl SolveResult {
+    pub fn value(&self) -> f64 {
+        self.value
+    }
+}
+
+/// Finds the lowest value of the unknown that reaches `target_balance`. Every evaluation uses
+/// the same seed, so the random returns stay the same while the unknown changes.
+pub fn solve(
+    config: &investment_config::Configuration,
+    goal: Goal,
+    seed: u64,
+    distributions: &dyn distributions::DistributionRepository,
+) -> Result<SolveResult, error::SimulationError> {
+    let target = config
+        .target_balance
+        .ok_or(error::SimulationError::MissingTargetBalance)?;
+    if let Some(probability) = goal.probability {
+        if !(probability > 0.0 && probability <= 1.0) {
+            return Err(error::SimulationError::Validation(vec![
+                error::FieldError::new("probability", "must be greater than 0 and at most 1"),
+            ]));
+        }
+    }
+
+    let evaluate = |value: f64| -> Result<Evaluation, error::SimulationError> {
+        evaluate(
+            &with_unknown(config, goal.solve_for, value),
+            goal,
+            target,
+            seed,
+            distributions,
+        )
+    };
+    let value = match goal.solve_for {
+        Unknown::Years => {
+            let mut years = None;
+            for candidate in 1..=validation::MAX_YEARS {
+                match evaluate(candidate as f64) {
+                    Ok(evaluation) if evaluation.reached => {
+                        years = Some(candidate as f64);
+                        break;
+                    }
+                    Ok(_) => {}
+                    // The inputs don't cover this plan, nor any longer one
+                    Err(
+                        error::SimulationError::NotEnoughValues(..)
+                        | error::SimulationError::NotEnoughHistory(_),
+                    ) => break,
+                    Err(error) => return Err(error),
+                }
+            }
+            years.ok_or(error::SimulationError::UnreachableTarget(goal.solve_for))?
+        }
+        Unknown::ReturnRate => bisect(MIN_RETURN_RATE, 1.0, MAX_RETURN_RATE, 1e-6, |value| {
+            Ok(evaluate(value)?.reached)
+        })?
+        .ok_or(error::SimulationError::UnreachableTarget(goal.solve_for))?,
+        Unknown::Contribution | Unknown::Deposit => {
+            let value = bisect(0.0, target.max(1.0), MAX_AMOUNT, 0.01, |value| {
+                Ok(evaluate(value)?.reached)
+            })?
+            .ok_or(error::SimulationError::UnreachableTarget(goal.solve_for))?;
+            // The deposit is a whole amount
+            if goal.solve_for == Unknown::Deposit {
+                value.ceil()
+            } else {
+                value
+            }
+        }
+    };
+    let evaluation = evaluate(value)?;
+
+    Ok(SolveResult {
+        solve_for: goal.solve_for,
+        value,
+        target_balance: target,
+        seed,
+        final_balance: evaluation.final_balance,
+        success_probability: evaluation.success_probability,
+    })
+}
+
+struct Evaluation {
+    reached: bool,
+    final_balance: Option<f64>,
+    success_probability: Option<f64>,
+}
+
+fn evaluate(
+    config: &investment_config::Configuration,
+    goal: Goal,
+    target: f64,
+    seed: u64,
+    distributions: &dyn distributions::DistributionRepository,
+) -> Result<Evaluation, error::SimulationError> {
+    match goal.probability {
+        Some(probability) => {
+            let simulations = config
+                .simulations
+                .unwrap_or(monte_carlo::DEFAULT_SIMULATIONS);
+            let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+            let mut successes = 0;
+            for _ in 0..simulations {
+                let snapshots = investment::annual_results(
+                    &config.investment(distributions, &mut rng)?.simulate()?,
+                );
+                if snapshots
+                    .last()
+                    .is_some_and(|snapshot| snapshot.final_balance() >= target)
+                {
+                    successes += 1;
+                }
+            }
+            let success_probability = successes as f64 / simulations as f64;
+            Ok(Evaluation {
+                reached: success_probability >= probability,
+                final_balance: None,
+                success_probability: Some(success_probability),
+            })
+        }
+        None => {
+            let final_balance = config.simulate(seed, distributions)?.result.final_balance();
+            Ok(Evaluation {
+                reached: final_balance >= target,
+                final_balance: Some(final_balance),
+                success_probability: None,
+            })
+        }
+    }
+}
+
+fn with_unknown(
+    config: &investment_config::Configuration,
+    unknown: Unknown,
+    value: f64,
+) -> investment_config::Configuration {
+    let mut config = config.clone();
+    match unknown {
+        Unknown::Contribution => {
+            config.annual_contributions =
+                types::AnnualContribution::Single(types::PositiveFloat(value))
+        }
+        Unknown::ReturnRate => {
+            config.return_rates = Some(types::Interest::Single(value));
+            config.portfolio = None;
+        }
+        Unknown::Deposit => config.deposit = value.ceil() as usize,
+        Unknown::Years => config.years = value as usize,
+    }
+    config
+}
+
+/// Lowest value between `low` and `limit` for which `reaches` holds, within `tolerance`. The
+/// upper bound starts at `high` and doubles until it reaches the target, `None` if it never
+/// does. `reaches` must not hold below the solution and hold above it.
+fn bisect<F>(
+    low: f64,
+    high: f64,
+    limit: f64,
+    tolerance: f64,
+    mut reaches: F,
+) -> Result<Option<f64>, error::SimulationError>
+where
+    F: FnMut(f64) -> Result<bool, error::SimulationError>,
+{
+    if reaches(low)? {
+        return Ok(Some(low));
+    }
+    let mut low = low;
+    let mut high = high;
+    while !reaches(high)? {
+        if high >= limit {
+            return Ok(None);
+        }
+        low = high;
+        high = (high * 2.0).min(limit);
+    }
+    for _ in 0..MAX_ITERATIONS {
+        if high - low <= tolerance {
+            break;
+        }
+        let middle = (low + high) / 2.0;
+        if reaches(middle)? {
+            high = middle;
+        } else {
+            low = middle;
+        }
+    }
+    Ok(Some(high))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{solve, Goal, Unknown};
+    use crate::distributions::{CsvRepository, DEFAULT_DIRECTORY};
+    use crate::error::SimulationError;
+    use crate::investment_config::Configuration;
+
+    fn config(value: serde_json::Value) -> Configuration {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_solve_deterministic_unknowns() {
+        let repository = CsvRepository::load(DEFAULT_DIRECTORY).unwrap();
+        let config = config(serde_json::json!({
+            "deposit": 10000,
+            "years": 2,
+            "return_rates": 0.1,
+            "annual_contributions": 1000.0,
+            "target_balance": 14400.0,
+        }));
+        let solve_for = |solve_for: Unknown| {
+            let goal = Goal {
+                solve_for,
+                probability: None,
+            };
+            solve(&config, goal, 0, &repository).unwrap().value()
+        };
+
+        // ((deposit + contribution) * 1.1 + contribution) * 1.1 = 14400
+        assert!((solve_for(Unknown::Contribution) - 2300.0 / 2.31).abs() <= 0.01);
+        let return_rate = solve_for(Unknown::ReturnRate);
+        assert!(return_rate > 0.09 && return_rate < 0.1);
+        assert_eq!(solve_for(Unknown::Deposit), 9992.0);
+        assert_eq!(solve_for(Unknown::Years), 2.0);
+    }
+
+    #[test]
+    fn test_solve_contribution_with_probability() {
+        let repository = CsvRepository::load(DEFAULT_DIRECTORY).unwrap();
+        let config = config(serde_json::json!({
+            "deposit": 0,
+            "years": 10,
+            "return_rates": "sp500",
+            "annual_contributions": 0.0,
+            "simulations": 100,
+            "target_balance": 100000.0,
+        }));
+        let solve_for = |probability: f64| {
+            let goal = Goal {
+                solve_for: Unknown::Contribution,
+                probability: Some(probability),
+            };
+            solve(&config, goal, 7, &repository).unwrap().value()
+        };
+
+        let likely = solve_for(0.5);
+        let almost_certain = solve_for(0.95);
+        assert!(likely > 0.0 && likely < 10000.0);
+        assert!(almost_certain > likely);
+    }
+
+    #[test]
+    fn test_unreachable_target() {
+        let repository = CsvRepository::load(DEFAULT_DIRECTORY).unwrap();
+        let config = config(serde_json::json!({
+            "deposit": 1000,
+            "years": 3,
+            "return_rates": -0.5,
+            "annual_contributions": 0.0,
+            "target_balance": 1e9,
+        }));
+        let goal = Goal {
+            solve_for: Unknown::Years,
+            probability: None,
+        };
+        assert!(solve(&config, goal, 0, &repository).is_err());
+    }
+
+    #[test]
+    fn test_years_are_limited_to_the_covered_horizon() {
+        let repository = CsvRepository::load(DEFAULT_DIRECTORY).unwrap();
+        let goal = Goal {
+            solve_for: Unknown::Years,
+            probability: None,
+        };
+        for return_rates in [
+            serde_json::json!([0.1, 0.1]),
+            serde_json::json!({"distribution": "sp500", "start_year": 2021}),
+        ] {
+            let config = config(serde_json::json!({
+                "deposit": 1000,
+                "years": 2,
+                "return_rates": return_rates,
+                "annual_contributions": 0.0,
+                "target_balance": 1e6,
+            }));
+            assert!(matches!(
+                solve(&config, goal, 0, &repository),
+                Err(SimulationError::UnreachableTarget(Unknown::Years))
+            ));
+        }
+    }
+}
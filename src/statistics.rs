@@ -0,0 +1,227 @@
+use crate::distributions;
+use crate::error;
+
+pub const DEFAULT_HISTOGRAM_BINS: usize = 10;
+pub const MAX_HISTOGRAM_BINS: usize = 1000;
+
+#[derive(serde::Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct YearValue {
+    year: i32,
+    value: f64,
+}
+
+/// Largest peak to trough fall of the growth of 1 invested at the start of the distribution
+#[derive(serde::Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct Drawdown {
+    start_year: i32,
+    end_year: i32,
+    years: usize,
+    drawdown: f64,
+}
+
+#[derive(serde::Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct HistogramBin {
+    lower: f64,
+    upper: f64,
+    count: usize,
+}
+
+/// Descriptive statistics of the yearly returns. The skewness and excess kurtosis are computed
+/// from the population moments.
+#[derive(serde::Serialize, Debug)]
+pub struct DistributionStatistics {
+    name: String,
+    years: usize,
+    start_year: i32,
+    end_year: i32,
+    arithmetic_mean: f64,
+    geometric_mean: f64,
+    standard_deviation: f64,
+    skewness: f64,
+    excess_kurtosis: f64,
+    min: YearValue,
+    max: YearValue,
+    worst_drawdown: Option<Drawdown>,
+    histogram: Vec<HistogramBin>,
+}
+
+pub fn distribution_statistics(
+    name: &str,
+    distribution: &distributions::Distribution,
+    bins: usize,
+) -> Result<DistributionStatistics, error::SimulationError> {
+    if bins == 0 || bins > MAX_HISTOGRAM_BINS {
+        return Err(error::SimulationError::Validation(vec![
+            error::FieldError::new(
+                "bins",
+                format!("must be between 1 and {}", MAX_HISTOGRAM_BINS),
+            ),
+        ]));
+    }
+    let values = &distribution.values;
+    if values.len() < 2 {
+        return Err(error::SimulationError::NotEnoughData(values.len()));
+    }
+    if values.iter().any(|value| *value <= -1.0) {
+        return Err(error::SimulationError::InvalidReturns);
+    }
+
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    let central_moment =
+        |power: i32| values.iter().map(|v| (v - mean).powi(power)).sum::<f64>() / n;
+    let (second, third, fourth) = (central_moment(2), central_moment(3), central_moment(4));
+    let growth: f64 = values.iter().map(|value| 1.0 + value).product();
+
+    let year_value = |index: usize| YearValue {
+        year: distribution.years[index],
+        value: values[index],
+    };
+    let min_index = (0..values.len())
+        .min_by(|&a, &b| values[a].total_cmp(&values[b]))
+        .unwrap();
+    let max_index = (0..values.len())
+        .max_by(|&a, &b| values[a].total_cmp(&values[b]))
+        .unwrap();
+
+    Ok(DistributionStatistics {
+        name: name.to_string(),
+        years: values.len(),
+        start_year: distribution.years[0],
+        end_year: distribution.years[values.len() - 1],
+        arithmetic_mean: mean,
+        geometric_mean: growth.powf(1.0 / n) - 1.0,
+        standard_deviation: (second * n / (n - 1.0)).sqrt(),
+        skewness: third / second.powf(1.5),
+        excess_kurtosis: fourth / second.powi(2) - 3.0,
+        min: year_value(min_index),
+        max: year_value(max_index),
+        worst_drawdown: worst_drawdown(distribution),
+        histogram: histogram(values, bins),
+    })
+}
+
+/// `None` when the returns never fall
+fn worst_drawdown(distribution: &distributions::Distribution) -> Option<Drawdown> {
+    let mut worst: Option<Drawdown> = None;
+    let mut peak = 1.0;
+    let mut peak_index = 0;
+    let mut wealth = 1.0;
+    for (i, value) in distribution.values.iter().enumerate() {
+        wealth *= 1.0 + value;
+        if wealth >= peak {
+            peak = wealth;
+            peak_index = i + 1;
+            continue;
+        }
+        let drawdown = wealth / peak - 1.0;
+        let is_worst = match worst {
+            Some(worst) => drawdown < worst.drawdown,
+            None => true,
+        };
+        if is_worst {
+            worst = Some(Drawdown {
+                start_year: distribution.years[peak_index],
+                end_year: distribution.years[i],
+                years: i + 1 - peak_index,
+                drawdown,
+            });
+        }
+    }
+    worst
+}
+
+/// Equal width bins between the lowest and highest values, the last bin includes the highest
+fn histogram(values: &[f64], bins: usize) -> Vec<HistogramBin> {
+    let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let width = (max - min) / bins as f64;
+
+    let mut histogram: Vec<HistogramBin> = (0..bins)
+        .map(|bin| HistogramBin {
+            lower: min + width * bin as f64,
+            upper: if bin + 1 == bins {
+                max
+            } else {
+                min + width * (bin + 1) as f64
+            },
+            count: 0,
+        })
+        .collect();
+    for value in values {
+        let bin = if width > 0.0 {
+            (((value - min) / width) as usize).min(bins - 1)
+        } else {
+            0
+        };
+        histogram[bin].count += 1;
+    }
+    histogram
+}
+
+#[cfg(test)]
+mod test {
+    use super::{distribution_statistics, Drawdown};
+    use crate::distributions::Distribution;
+    use assert_float_eq::assert_f64_near;
+
+    #[test]
+    fn test_distribution_statistics() {
+        let distribution = Distribution {
+            years: vec![2000, 2001, 2002, 2003],
+            values: vec![0.1, -0.2, -0.1, 0.5],
+        };
+        let statistics = distribution_statistics("test", &distribution, 2).unwrap();
+
+        assert_f64_near!(statistics.arithmetic_mean, 0.075);
+        assert_f64_near!(
+            statistics.geometric_mean,
+            (1.1f64 * 0.8 * 0.9 * 1.5).powf(0.25) - 1.0
+        );
+        assert_eq!(statistics.min.year, 2001);
+        assert_eq!(statistics.max.year, 2003);
+        assert_eq!(
+            statistics
+                .histogram
+                .iter()
+                .map(|bin| bin.count)
+                .collect::<Vec<usize>>(),
+            vec![3, 1]
+        );
+
+        // From 1.1 down to 0.792
+        let Drawdown {
+            start_year,
+            end_year,
+            years,
+            drawdown,
+        } = statistics.worst_drawdown.unwrap();
+        assert_eq!((start_year, end_year, years), (2001, 2002, 2));
+        assert_f64_near!(drawdown, 0.792 / 1.1 - 1.0);
+    }
+
+    #[test]
+    fn test_statistics_need_two_values() {
+        let distribution = Distribution {
+            years: vec![2000],
+            values: vec![0.1],
+        };
+        assert!(distribution_statistics("test", &distribution, 10).is_err());
+    }
+
+    #[test]
+    fn test_histogram_bins_are_bounded() {
+        let distribution = Distribution {
+            years: vec![2000, 2001],
+            values: vec![0.1, -0.2],
+        };
+        for bins in [0, super::MAX_HISTOGRAM_BINS + 1, usize::MAX] {
+            match distribution_statistics("test", &distribution, bins) {
+                Err(crate::error::SimulationError::Validation(errors)) => {
+                    assert_eq!(errors[0].field, "bins")
+                }
+                _ => panic!("{} bins must be rejected", bins),
+            }
+        }
+    }
+}
@@ -0,0 +1,253 @@
+use crate::distributions;
+use crate::error;
+use crate::investment;
+use crate::investment_config;
+use crate::validation;
+use std::collections::BTreeMap;
+
+/// Largest number of combinations simulated by a single sweep
+pub const MAX_COMBINATIONS: usize = 10000;
+
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+pub struct SweepRequest {
+    #[serde(flatten)]
+    pub config: investment_config::Configuration,
+    /// Values taken by every swept field of the configuration
+    pub sweep: BTreeMap<String, Vec<serde_json::Value>>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, clap::ValueEnum, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Format {
+    Json,
+    Csv,
+}
+
+#[derive(serde::Serialize, Debug)]
+pub struct SweepRow {
+    parameters: BTreeMap<String, serde_json::Value>,
+    result: investment::InvestmentResult,
+}
+
+#[derive(serde::Serialize, Debug)]
+pub struct SweepResult {
+    seed: u64,
+    combinations: usize,
+    rows: Vec<SweepRow>,
+}
+
+/// Simulates every combination of the swept values with the same seed, the rest of the fields
+/// keep the value of `config`
+pub fn run_sweep(
+    config: &investment_config::Configuration,
+    sweep: &BTreeMap<String, Vec<serde_json::Value>>,
+    seed: u64,
+    distributions: &dyn distributions::DistributionRepository,
+) -> Result<SweepResult, error::SimulationError> {
+    let base =
+        serde_json::to_value(config).map_err(|error| sweep_error("sweep", error.to_string()))?;
+    for (field, values) in sweep.iter() {
+        if base.get(field).is_none() {
+            return Err(sweep_error(
+                &format!("sweep.{}", field),
+                "isn't a configuration field",
+            ));
+        }
+        if values.is_empty() {
+            return Err(sweep_error(
+                &format!("sweep.{}", field),
+                "needs at least one value",
+            ));
+        }
+    }
+    let combinations = sweep
+        .values()
+        .try_fold(1usize, |total, values| total.checked_mul(values.len()))
+        .filter(|combinations| *combinations <= MAX_COMBINATIONS)
+        .ok_or_else(|| {
+            sweep_error(
+                "sweep",
+                format!("has more than {} combinations", MAX_COMBINATIONS),
+            )
+        })?;
+
+    let mut rows = Vec::with_capacity(combinations);
+    for index in 0..combinations {
+        // Mixed radix decomposition of the index, the last field changes the fastest
+        let mut parameters = BTreeMap::new();
+        let mut remainder = index;
+        for (field, values) in sweep.iter().rev() {
+            parameters.insert(field.clone(), values[remainder % values.len()].clone());
+            remainder /= values.len();
+        }
+
+        let mut combination = base.clone();
+        for (field, value) in parameters.iter() {
+            combination[field] = value.clone();
+        }
+        let combination: investment_config::Configuration = serde_json::from_value(combination)
+            .map_err(|error| sweep_error("sweep", error.to_string()))?;
+        validation::validate_configuration(&combination, distributions)?;
+        rows.push(SweepRow {
+            parameters,
+            result: combination.simulate(seed, distributions)?.result,
+        });
+    }
+
+    Ok(SweepResult {
+        seed,
+        combinations,
+        rows,
+    })
+}
+
+fn sweep_error(field: &str, reason: impl Into<String>) -> error::SimulationError {
+    error::SimulationError::Validation(vec![error::FieldError::new(field, reason)])
+}
+
+impl SweepResult {
+    /// One row per combination with a column per swept field followed by the scalar fields of
+    /// the result, the nested ones joined with dots. Values missing in a row are left empty.
+    pub fn to_csv(&self) -> Result<String, error::ApplicationError> {
+        let mut rows: Vec<BTreeMap<String, String>> = Vec::with_capacity(self.rows.len());
+        let mut header: Vec<String> = Vec::new();
+        for row in self.rows.iter() {
+            let mut columns: Vec<(String, String)> = row
+                .parameters
+                .iter()
+                .map(|(field, value)| (field.clone(), cell(value)))
+                .collect();
+            flatten("", &serde_json::to_value(&row.result)?, &mut columns);
+            for (name, _) in columns.iter() {
+                if !header.contains(name) {
+                    header.push(name.clone());
+                }
+            }
+            rows.push(columns.into_iter().collect());
+        }
+
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        writer.write_record(&header).map_err(csv_error)?;
+        for row in rows.iter() {
+            writer
+                .write_record(
+                    header
+                        .iter()
+                        .map(|name| row.get(name).map_or("", String::as_str)),
+                )
+                .map_err(csv_error)?;
+        }
+
+        String::from_utf8(writer.into_inner().map_err(csv_error)?).map_err(csv_error)
+    }
+}
+
+fn csv_error(error: impl std::fmt::Display) -> error::ApplicationError {
+    error::ApplicationError::SerializationError(serde::ser::Error::custom(error))
+}
+
+fn flatten(prefix: &str, value: &serde_json::Value, columns: &mut Vec<(String, String)>) {
+    match value {
+        serde_json::Value::Object(fields) => {
+            for (name, value) in fields {
+                let name = if prefix.is_empty() {
+                    name.clone()
+                } else {
+                    format!("{}.{}", prefix, name)
+                };
+                flatten(&name, value, columns);
+            }
+        }
+        serde_json::Value::Array(_) => {}
+        value => columns.push((prefix.to_string(), cell(value))),
+    }
+}
+
+fn cell(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::Null => String::new(),
+        serde_json::Value::String(text) => text.clone(),
+        value => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::run_sweep;
+    use crate::distributions::{CsvRepository, DEFAULT_DIRECTORY};
+    use crate::investment_config::Configuration;
+    use assert_float_eq::assert_f64_near;
+
+    #[test]
+    fn test_sweep_runs_every_combination() {
+        let config: Configuration = serde_json::from_value(serde_json::json!({
+            "deposit": 10000,
+            "years": 1,
+            "return_rates": 0.05,
+            "annual_contributions": 0.0,
+        }))
+        .unwrap();
+        let sweep = serde_json::from_value(serde_json::json!({
+            "years": [1, 2],
+            "return_rates": [0.1, 0.2, 0.3],
+        }))
+        .unwrap();
+        let repository = CsvRepository::load(DEFAULT_DIRECTORY).unwrap();
+        let result = run_sweep(&config, &sweep, 0, &repository).unwrap();
+
+        assert_eq!(result.combinations, 6);
+        assert_eq!(result.rows[1].parameters["return_rates"], 0.1);
+        assert_eq!(result.rows[1].parameters["years"], 2);
+        assert_f64_near!(result.rows[1].result.final_balance(), 12100.0, 10);
+        assert_f64_near!(result.rows[4].result.final_balance(), 13000.0, 10);
+
+        let csv = result.to_csv().unwrap();
+        let mut lines = csv.lines();
+        assert!(lines
+            .next()
+            .unwrap()
+            .starts_with("return_rates,years,after_tax_final_balance"));
+        assert_eq!(lines.count(), 6);
+    }
+
+    #[test]
+    fn test_csv_rows_with_different_fields() {
+        let config: Configuration = serde_json::from_value(serde_json::json!({
+            "deposit": 10000,
+            "years": 2,
+            "return_rates": 0.05,
+            "annual_contributions": 0.0,
+        }))
+        .unwrap();
+        // Only the falling returns have a drawdown
+        let sweep = serde_json::from_value(serde_json::json!({
+            "return_rates": [0.05, -0.05],
+        }))
+        .unwrap();
+        let repository = CsvRepository::load(DEFAULT_DIRECTORY).unwrap();
+        let csv = run_sweep(&config, &sweep, 0, &repository)
+            .unwrap()
+            .to_csv()
+            .unwrap();
+
+        let mut reader = csv::Reader::from_reader(csv.as_bytes());
+        let columns = reader.headers().unwrap().len();
+        assert!(reader
+            .records()
+            .all(|record| record.unwrap().len() == columns));
+    }
+
+    #[test]
+    fn test_unknown_fields_are_rejected() {
+        let config: Configuration = serde_json::from_value(serde_json::json!({
+            "deposit": 10000,
+            "years": 1,
+            "return_rates": 0.05,
+            "annual_contributions": 0.0,
+        }))
+        .unwrap();
+        let sweep = serde_json::from_value(serde_json::json!({"unknown": [1, 2]})).unwrap();
+        let repository = CsvRepository::load(DEFAULT_DIRECTORY).unwrap();
+        assert!(run_sweep(&config, &sweep, 0, &repository).is_err());
+    }
+}
@@ -1,5 +1,6 @@
 use crate::distributions;
 use crate::error;
+use crate::return_models;
 use fake::{Dummy, Faker};
 use rand::Rng;
 
@@ -24,45 +25,270 @@ impl Dummy<Faker> for PositiveFloat {
     }
 }
 
-#[derive(serde::Deserialize)]
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
 #[serde(untagged)]
 pub enum Interest {
     Single(f64),
     Multiple(Vec<f64>),
     Distribution(String),
+    /// Replays the distribution in order, starting at `start_year`
+    Historical {
+        distribution: String,
+        start_year: i32,
+    },
+    /// Draws blocks of consecutive years to keep the streaks of bull and bear markets
+    Bootstrap {
+        distribution: String,
+        block_size: usize,
+        #[serde(default)]
+        method: BootstrapMethod,
+    },
+    /// Parametric model, e.g. `{"model": "normal", "mean": 0.07, "volatility": 0.15}`
+    Model(return_models::ReturnModel),
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BootstrapMethod {
+    /// Every block has exactly `block_size` years
+    #[default]
+    Fixed,
+    /// Block lengths are random, following a geometric distribution with mean `block_size`
+    Stationary,
+}
+
+/// How the historical years of a distribution are drawn
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Sampling {
+    /// Years are drawn independently and with replacement
+    Independent,
+    /// Blocks of consecutive years are drawn, wrapping around the end of the history
+    Block {
+        block_size: usize,
+        method: BootstrapMethod,
+    },
+}
+
+impl Sampling {
+    /// Indices of the sampled years in a history of `length` years
+    fn sample_indices<R: Rng + ?Sized>(
+        &self,
+        length: usize,
+        years: usize,
+        rng: &mut R,
+    ) -> Vec<usize> {
+        match *self {
+            Sampling::Independent => (0..years).map(|_| rng.gen_range(0..length)).collect(),
+            Sampling::Block { block_size, method } => {
+                let block_size = block_size.max(1);
+                let mut indices: Vec<usize> = Vec::with_capacity(years);
+                let mut index = rng.gen_range(0..length);
+                let mut block_length = 0;
+                while indices.len() < years {
+                    let new_block = match method {
+                        BootstrapMethod::Fixed => block_length == block_size,
+                        BootstrapMethod::Stationary => {
+                            block_length > 0 && rng.gen_bool(1.0 / block_size as f64)
+                        }
+                    };
+                    if new_block {
+                        index = rng.gen_range(0..length);
+                        block_length = 0;
+                    }
+                    indices.push(index);
+                    index = (index + 1) % length;
+                    block_length += 1;
+                }
+                indices
+            }
+        }
+    }
 }
 
 impl Interest {
-    pub fn to_interest_rates(&self, years: usize) -> Vec<f64> {
+    pub fn to_interest_rates<R: Rng + ?Sized>(
+        &self,
+        years: usize,
+        distributions: &dyn distributions::DistributionRepository,
+        rng: &mut R,
+    ) -> Result<Vec<f64>, error::SimulationError> {
+        self.to_rates(years, distributions::Series::Returns, distributions, rng)
+    }
+
+    /// Rates of the interest, its distribution is looked up in `series`
+    pub fn to_rates<R: Rng + ?Sized>(
+        &self,
+        years: usize,
+        series: distributions::Series,
+        distributions: &dyn distributions::DistributionRepository,
+        rng: &mut R,
+    ) -> Result<Vec<f64>, error::SimulationError> {
         match self {
             Interest::Single(fixed_interest) => {
-                (0..years).map(|_| *fixed_interest).collect::<Vec<f64>>()
+                Ok((0..years).map(|_| *fixed_interest).collect::<Vec<f64>>())
             }
-            Interest::Multiple(multiple) => multiple.to_vec(),
-            Interest::Distribution(dist_name) => {
-                let distribution = distributions::get_distributions()
-                    .get(dist_name.as_str())
-                    .expect("The selected distribution doesn't exist")
-                    .to_vec();
-
-                let mut rng = rand::thread_rng();
-                let mut distribution_simulation: Vec<f64> = Vec::with_capacity(years);
-
-                for _ in 0..years {
-                    let random_index = rng.gen_range(0..distribution.len());
-                    distribution_simulation.push(distribution[random_index]);
-                }
-                distribution_simulation
+            Interest::Multiple(multiple) => Ok(multiple.to_vec()),
+            Interest::Distribution(dist_name) => Ok(joint_distribution_rates(
+                &[distributions.get_series(series, dist_name)?],
+                years,
+                Sampling::Independent,
+                rng,
+            )?
+            .remove(0)),
+            Interest::Bootstrap { distribution, .. } => Ok(joint_distribution_rates(
+                &[distributions.get_series(series, distribution)?],
+                years,
+                self.sampling().unwrap(),
+                rng,
+            )?
+            .remove(0)),
+            Interest::Model(model) => model.sample(years, rng),
+            Interest::Historical {
+                distribution,
+                start_year,
+            } => {
+                let distribution = distributions.get_series(series, distribution)?;
+
+                (*start_year..*start_year + years as i32)
+                    .map(|year| {
+                        distribution
+                            .value_for_year(year)
+                            .ok_or(error::SimulationError::NotEnoughHistory(years))
+                    })
+                    .collect()
             }
         }
     }
+
+    /// Name of the historical distribution the rates are drawn from, if any
+    pub fn distribution_name(&self) -> Option<&str> {
+        match self {
+            Interest::Distribution(name) => Some(name.as_str()),
+            Interest::Historical { distribution, .. } => Some(distribution.as_str()),
+            Interest::Bootstrap { distribution, .. } => Some(distribution.as_str()),
+            _ => None,
+        }
+    }
+
+    /// How the historical years are drawn, for the rates randomly sampled from a distribution
+    pub fn sampling(&self) -> Option<Sampling> {
+        match self {
+            Interest::Distribution(_) => Some(Sampling::Independent),
+            Interest::Bootstrap {
+                block_size, method, ..
+            } => Some(Sampling::Block {
+                block_size: *block_size,
+                method: *method,
+            }),
+            _ => None,
+        }
+    }
 }
 
-#[derive(serde::Deserialize)]
+/// Rates of every interest, each looked up in its series. The interests sampled from historical
+/// distributions in the same way draw the same years, keeping the real correlation between them,
+/// e.g. between the assets of a portfolio or between the returns and the inflation.
+pub fn sampled_rates<R: Rng + ?Sized>(
+    interests: &[(&Interest, distributions::Series)],
+    years: usize,
+    distributions: &dyn distributions::DistributionRepository,
+    rng: &mut R,
+) -> Result<Vec<Vec<f64>>, error::SimulationError> {
+    let mut samplings: Vec<Sampling> = Vec::new();
+    for sampling in interests
+        .iter()
+        .filter_map(|(interest, _)| interest.sampling())
+    {
+        if !samplings.contains(&sampling) {
+            samplings.push(sampling);
+        }
+    }
+
+    let mut rates: Vec<Option<Vec<f64>>> = vec![None; interests.len()];
+    for sampling in samplings {
+        let group: Vec<usize> = (0..interests.len())
+            .filter(|&i| interests[i].0.sampling() == Some(sampling))
+            .collect();
+        let selected = group
+            .iter()
+            .map(|&i| {
+                let (interest, series) = interests[i];
+                distributions.get_series(series, interest.distribution_name().unwrap_or_default())
+            })
+            .collect::<Result<Vec<&distributions::Distribution>, error::SimulationError>>()?;
+        let sampled = joint_distribution_rates(&selected, years, sampling, rng)?;
+        for (i, sampled) in group.into_iter().zip(sampled) {
+            rates[i] = Some(sampled);
+        }
+    }
+
+    interests
+        .iter()
+        .zip(rates)
+        .map(|(&(interest, series), rates)| match rates {
+            Some(rates) => Ok(rates),
+            None => interest.to_rates(years, series, distributions, rng),
+        })
+        .collect()
+}
+
+/// Samples historical years shared by all the distributions and returns, for every
+/// distribution, its value on each sampled year. Drawing the same year for every distribution
+/// keeps the real correlation between them.
+pub fn joint_distribution_rates<R: Rng + ?Sized>(
+    selected: &[&distributions::Distribution],
+    years: usize,
+    sampling: Sampling,
+    rng: &mut R,
+) -> Result<Vec<Vec<f64>>, error::SimulationError> {
+    if selected.is_empty() {
+        return Ok(Vec::new());
+    }
+    let common_years = shared_years(selected);
+    if common_years.is_empty() {
+        return Err(error::SimulationError::NoSharedYears);
+    }
+
+    let sampled_years: Vec<i32> = sampling
+        .sample_indices(common_years.len(), years, rng)
+        .into_iter()
+        .map(|index| common_years[index])
+        .collect();
+
+    Ok(selected
+        .iter()
+        .map(|distribution| {
+            sampled_years
+                .iter()
+                .map(|&year| distribution.value_for_year(year).unwrap())
+                .collect()
+        })
+        .collect())
+}
+
+/// Years present in every one of the distributions
+pub fn shared_years(selected: &[&distributions::Distribution]) -> Vec<i32> {
+    match selected.first() {
+        Some(first) => first
+            .years
+            .iter()
+            .copied()
+            .filter(|year| {
+                selected
+                    .iter()
+                    .all(|distribution| distribution.years.contains(year))
+            })
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
 #[serde(untagged)]
 pub enum AnnualContribution {
     Single(PositiveFloat),
     Multiple(Vec<PositiveFloat>),
+    Schedule(ContributionSchedule),
 }
 
 impl AnnualContribution {
@@ -72,15 +298,353 @@ impl AnnualContribution {
                 (0..times).map(|_| *fixed_contribution).collect()
             }
             AnnualContribution::Multiple(multiple) => multiple.to_vec(),
+            AnnualContribution::Schedule(schedule) => schedule.to_annual_contributions(times),
+        }
+    }
+
+    /// Every contribution multiplied by `factor`
+    pub fn scaled(&self, factor: f64) -> Self {
+        match self {
+            AnnualContribution::Single(contribution) => {
+                AnnualContribution::Single(PositiveFloat(contribution.0 * factor))
+            }
+            AnnualContribution::Multiple(multiple) => AnnualContribution::Multiple(
+                multiple
+                    .iter()
+                    .map(|contribution| PositiveFloat(contribution.0 * factor))
+                    .collect(),
+            ),
+            AnnualContribution::Schedule(schedule) => {
+                AnnualContribution::Schedule(ContributionSchedule {
+                    amount: PositiveFloat(schedule.amount.0 * factor),
+                    steps: schedule
+                        .steps
+                        .iter()
+                        .map(|step| ContributionStep {
+                            year: step.year,
+                            amount: PositiveFloat(step.amount.0 * factor),
+                        })
+                        .collect(),
+                    ..schedule.clone()
+                })
+            }
+        }
+    }
+
+    /// Contributions following inflation grow with the accumulated inflation
+    pub fn follows_inflation(&self) -> bool {
+        matches!(
+            self,
+            AnnualContribution::Schedule(ContributionSchedule {
+                growth: ContributionGrowth::Inflation(_),
+                ..
+            })
+        )
+    }
+}
+
+/// Contribution of `amount` in the first year, growing every year by `growth`. From the year of
+/// every step on, the contribution restarts from the step amount. Contributions stop after
+/// `years` years, if set. Years start at 0 like the withdrawals `start_year`.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct ContributionSchedule {
+    pub amount: PositiveFloat,
+    #[serde(default)]
+    pub growth: ContributionGrowth,
+    #[serde(default)]
+    pub steps: Vec<ContributionStep>,
+    pub years: Option<usize>,
+}
+
+impl ContributionSchedule {
+    fn to_annual_contributions(&self, times: usize) -> Vec<PositiveFloat> {
+        let growth_rate = match self.growth {
+            ContributionGrowth::Rate(rate) => rate,
+            ContributionGrowth::Inflation(_) => 0.0,
+        };
+        let mut steps = self.steps.clone();
+        steps.sort_by_key(|step| step.year);
+
+        (0..times)
+            .map(|year| {
+                if self.years.is_some_and(|years| year >= years) {
+                    return PositiveFloat(0.0);
+                }
+                let (start_year, amount) = steps
+                    .iter()
+                    .rev()
+                    .find(|step| step.year <= year)
+                    .map_or((0, self.amount.0), |step| (step.year, step.amount.0));
+                PositiveFloat(amount * (1.0 + growth_rate).powi((year - start_year) as i32))
+            })
+            .collect()
+    }
+}
+
+/// A yearly growth rate or `"inflation"`
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(untagged)]
+pub enum ContributionGrowth {
+    Rate(f64),
+    Inflation(InflationGrowth),
+}
+
+impl Default for ContributionGrowth {
+    fn default() -> Self {
+        ContributionGrowth::Rate(0.0)
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum InflationGrowth {
+    Inflation,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct ContributionStep {
+    pub year: usize,
+    pub amount: PositiveFloat,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct Asset {
+    pub name: String,
+    pub weight: f64,
+    pub return_rates: Interest,
+}
+
+/// Length of every simulated step. Annual rates are converted to their periodic equivalents and
+/// the yearly contributions are split evenly across the periods.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, Default, PartialEq, Dummy)]
+#[serde(rename_all = "snake_case")]
+pub enum Granularity {
+    #[default]
+    Annual,
+    Monthly,
+    Weekly,
+}
+
+impl Granularity {
+    pub fn periods_per_year(&self) -> usize {
+        match self {
+            Granularity::Annual => 1,
+            Granularity::Monthly => 12,
+            Granularity::Weekly => 52,
+        }
+    }
+}
+
+/// When the contribution of every period is invested. Investing at the start gives the
+/// annuity-due future value and investing at the end the ordinary annuity one.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, Default, PartialEq, Dummy)]
+#[serde(rename_all = "snake_case")]
+pub enum ContributionTiming {
+    #[default]
+    Start,
+    Middle,
+    End,
+}
+
+/// Rate that compounded `periods` times gives `annual_rate`. Losing everything in a year means
+/// losing everything in its first period.
+pub fn periodic_rate(annual_rate: f64, periods: usize) -> f64 {
+    if periods == 1 {
+        return annual_rate;
+    }
+    if annual_rate <= -1.0 {
+        return -1.0;
+    }
+    (1.0 + annual_rate).powf(1.0 / periods as f64) - 1.0
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, Default, PartialEq, Dummy)]
+#[serde(rename_all = "snake_case")]
+pub enum Rebalancing {
+    #[default]
+    Never,
+    /// The target weights are restored at the start of every year
+    Annually,
+    /// The target weights are restored when any asset drifts more than this away from its target
+    Threshold(f64),
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct Portfolio {
+    pub assets: Vec<Asset>,
+    #[serde(default)]
+    pub rebalancing: Rebalancing,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, Default, Dummy)]
+#[serde(default)]
+pub struct BrokerageFee {
+    /// Flat amount charged on every contribution
+    pub flat: f64,
+    /// Share of every contribution charged on top of the flat amount
+    pub rate: f64,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, Default, Dummy)]
+#[serde(default)]
+pub struct Fees {
+    /// Annual fund expense ratio (TER), deducted from the balance every year
+    pub expense_ratio: f64,
+    pub brokerage_fee: BrokerageFee,
+    /// Annual custody fee, deducted from the balance every year
+    pub custody_fee: f64,
+}
+
+impl Fees {
+    /// Fee charged when contributing `amount`, it never exceeds the amount itself
+    pub fn brokerage_fee(&self, amount: f64) -> f64 {
+        if amount <= 0.0 {
+            return 0.0;
+        }
+        (self.brokerage_fee.flat + self.brokerage_fee.rate * amount).min(amount)
+    }
+
+    /// Share of the balance charged every year
+    pub fn annual_fee_rate(&self) -> f64 {
+        self.expense_ratio + self.custody_fee
+    }
+}
+
+/// How the gains and withdrawals of the account are taxed
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, Default, PartialEq, Dummy)]
+#[serde(rename_all = "snake_case")]
+pub enum AccountType {
+    /// Brokerage account, dividends are taxed every year and the gains when withdrawn
+    #[default]
+    Taxable,
+    /// Pension-like account, the whole withdrawal is taxed as income
+    TaxDeferred,
+    /// Nothing is taxed
+    TaxFree,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, Default, Dummy)]
+#[serde(default)]
+pub struct Taxes {
+    pub account: AccountType,
+    /// Tax on the gains realised by withdrawals from a taxable account
+    pub capital_gains_rate: f64,
+    /// Share of the balance paid as dividends every year, reinvested after taxes
+    pub dividend_yield: f64,
+    pub dividend_tax_rate: f64,
+    /// Tax on the withdrawals from a tax-deferred account
+    pub income_tax_rate: f64,
+}
+
+impl Taxes {
+    /// Share of the balance lost every year to the taxes on dividends
+    pub fn dividend_tax_drag(&self) -> f64 {
+        match self.account {
+            AccountType::Taxable => self.dividend_yield * self.dividend_tax_rate,
+            _ => 0.0,
+        }
+    }
+
+    /// Tax due when withdrawing `amount` out of `balance`, `cost_basis` being the money
+    /// invested in it
+    pub fn withdrawal_tax(&self, amount: f64, balance: f64, cost_basis: f64) -> f64 {
+        match self.account {
+            AccountType::Taxable if balance > 0.0 => {
+                let gains = amount - cost_basis * amount / balance;
+                gains.max(0.0) * self.capital_gains_rate
+            }
+            AccountType::TaxDeferred => amount.max(0.0) * self.income_tax_rate,
+            _ => 0.0,
+        }
+    }
+
+    /// Balance left after withdrawing everything and paying the taxes due
+    pub fn after_tax_balance(&self, balance: f64, cost_basis: f64) -> f64 {
+        balance - self.withdrawal_tax(balance, balance, cost_basis)
+    }
+}
+
+fn default_guardrail() -> f64 {
+    0.2
+}
+
+fn default_adjustment() -> f64 {
+    0.1
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Dummy)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum WithdrawalStrategy {
+    /// The same amount is withdrawn every year
+    Fixed { amount: PositiveFloat },
+    /// A fixed share of the balance is withdrawn every year, e.g. the 4% rule
+    Percentage { rate: f64 },
+    /// Guyton-Klinger guardrails: the previous withdrawal is kept unless the current withdrawal
+    /// rate drifts more than `guardrail` away from `initial_rate`, then it's cut or raised by
+    /// `adjustment`. Raises are skipped after a year with negative returns.
+    GuytonKlinger {
+        initial_rate: f64,
+        #[serde(default = "default_guardrail")]
+        guardrail: f64,
+        #[serde(default = "default_adjustment")]
+        adjustment: f64,
+    },
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Dummy)]
+pub struct Withdrawal {
+    #[serde(default)]
+    pub start_year: usize,
+    pub strategy: WithdrawalStrategy,
+}
+
+impl Withdrawal {
+    pub fn withdrawal_amount(
+        &self,
+        balance: f64,
+        previous_withdrawal: Option<f64>,
+        previous_return_rate: Option<f64>,
+    ) -> f64 {
+        match self.strategy {
+            WithdrawalStrategy::Fixed { amount } => amount.0,
+            WithdrawalStrategy::Percentage { rate } => balance.max(0.0) * rate,
+            WithdrawalStrategy::GuytonKlinger {
+                initial_rate,
+                guardrail,
+                adjustment,
+            } => match previous_withdrawal {
+                None => balance.max(0.0) * initial_rate,
+                Some(previous) => {
+                    let current_rate = previous / balance;
+                    if current_rate > initial_rate * (1.0 + guardrail) {
+                        previous * (1.0 - adjustment)
+                    } else if current_rate < initial_rate * (1.0 - guardrail)
+                        && !matches!(previous_return_rate, Some(rate) if rate < 0.0)
+                    {
+                        previous * (1.0 + adjustment)
+                    } else {
+                        previous
+                    }
+                }
+            },
         }
     }
 }
 
 #[cfg(test)]
 mod test {
-    use super::{Interest, PositiveFloat};
+    use super::{
+        joint_distribution_rates, sampled_rates, Interest, PositiveFloat, Withdrawal,
+        WithdrawalStrategy,
+    };
+    use crate::distributions::{CsvRepository, DistributionRepository, Series, DEFAULT_DIRECTORY};
+    use assert_float_eq::assert_f64_near;
     use claim::assert_ok_eq;
-    use rand::Rng;
+    use rand::{Rng, SeedableRng};
+
+    fn repository() -> CsvRepository {
+        CsvRepository::load(DEFAULT_DIRECTORY).unwrap()
+    }
 
     #[derive(Clone, Debug)]
     struct ValidNumberFixture(pub f64);
@@ -117,11 +681,15 @@ mod test {
 
     #[test]
     fn test_single_interest_to_interest_rates() {
-        let interest_rates = Interest::Single(0.5).to_interest_rates(4);
+        let interest_rates = Interest::Single(0.5)
+            .to_interest_rates(4, &repository(), &mut rand::thread_rng())
+            .unwrap();
         assert_eq!(interest_rates.len(), 4);
         assert!(!interest_rates.is_empty());
 
-        let interest_rates = Interest::Single(0.5).to_interest_rates(0);
+        let interest_rates = Interest::Single(0.5)
+            .to_interest_rates(0, &repository(), &mut rand::thread_rng())
+            .unwrap();
         assert_eq!(interest_rates.len(), 0);
         assert!(interest_rates.is_empty())
     }
@@ -129,19 +697,303 @@ mod test {
     #[test]
     fn test_multiple_interest_to_interest_rates() {
         let interest_rates = Interest::Multiple(vec![0.5, 0.0, 0.2]);
-        assert_eq!(interest_rates.to_interest_rates(1), vec![0.5, 0.0, 0.2])
+        assert_eq!(
+            interest_rates
+                .to_interest_rates(1, &repository(), &mut rand::thread_rng())
+                .unwrap(),
+            vec![0.5, 0.0, 0.2]
+        )
     }
 
     #[test]
     fn test_distribution_to_interest_rates() {
         let interest = Interest::Distribution("sp500".to_string());
-        assert_eq!(interest.to_interest_rates(3).len(), 3);
+        assert_eq!(
+            interest
+                .to_interest_rates(3, &repository(), &mut rand::thread_rng())
+                .unwrap()
+                .len(),
+            3
+        );
+    }
+
+    #[test]
+    fn test_seeded_distribution_to_interest_rates() {
+        let interest = Interest::Distribution("sp500".to_string());
+        let first = interest
+            .to_interest_rates(
+                10,
+                &repository(),
+                &mut rand::rngs::StdRng::seed_from_u64(42),
+            )
+            .unwrap();
+        let second = interest
+            .to_interest_rates(
+                10,
+                &repository(),
+                &mut rand::rngs::StdRng::seed_from_u64(42),
+            )
+            .unwrap();
+        let other = interest
+            .to_interest_rates(10, &repository(), &mut rand::rngs::StdRng::seed_from_u64(7))
+            .unwrap();
+
+        assert_eq!(first, second);
+        assert_ne!(first, other);
+    }
+
+    #[test]
+    fn test_historical_to_interest_rates() {
+        let repository = repository();
+        let sp500 = repository.get("sp500").unwrap();
+        let interest = Interest::Historical {
+            distribution: "sp500".to_string(),
+            start_year: 1995,
+        };
+
+        assert_eq!(
+            interest
+                .to_interest_rates(3, &repository, &mut rand::thread_rng())
+                .unwrap(),
+            sp500.values[2..5].to_vec()
+        );
+    }
+
+    #[test]
+    fn test_fixed_block_sampling() {
+        let sampling = super::Sampling::Block {
+            block_size: 5,
+            method: super::BootstrapMethod::Fixed,
+        };
+        let indices = sampling.sample_indices(30, 20, &mut rand::rngs::StdRng::seed_from_u64(1));
+
+        assert_eq!(indices.len(), 20);
+        for block in indices.chunks(5) {
+            for pair in block.windows(2) {
+                assert_eq!(pair[1], (pair[0] + 1) % 30);
+            }
+        }
+    }
+
+    #[test]
+    fn test_stationary_block_sampling() {
+        let sampling = super::Sampling::Block {
+            block_size: 4,
+            method: super::BootstrapMethod::Stationary,
+        };
+        let indices = sampling.sample_indices(30, 10000, &mut rand::rngs::StdRng::seed_from_u64(1));
+        let consecutive = indices
+            .windows(2)
+            .filter(|pair| pair[1] == (pair[0] + 1) % 30)
+            .count();
+
+        // A new block starts with probability 1/4, so ~75% of the years continue the block
+        let ratio = consecutive as f64 / indices.len() as f64;
+        assert!(ratio > 0.7 && ratio < 0.8);
+    }
+
+    #[test]
+    fn test_model_to_interest_rates() {
+        let interest: Interest = serde_json::from_value(serde_json::json!({
+            "model": "geometric_brownian_motion",
+            "drift": 0.07,
+            "volatility": 0.2,
+        }))
+        .unwrap();
+        let first = interest
+            .to_interest_rates(5, &repository(), &mut rand::rngs::StdRng::seed_from_u64(5))
+            .unwrap();
+        let second = interest
+            .to_interest_rates(5, &repository(), &mut rand::rngs::StdRng::seed_from_u64(5))
+            .unwrap();
+
+        assert_eq!(first.len(), 5);
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn test_bootstrap_deserialization() {
+        let interest: Interest = serde_json::from_value(serde_json::json!({
+            "distribution": "sp500",
+            "block_size": 5,
+            "method": "stationary",
+        }))
+        .unwrap();
+
+        assert_eq!(
+            interest.sampling(),
+            Some(super::Sampling::Block {
+                block_size: 5,
+                method: super::BootstrapMethod::Stationary
+            })
+        );
+        assert_eq!(
+            interest
+                .to_interest_rates(12, &repository(), &mut rand::thread_rng())
+                .unwrap()
+                .len(),
+            12
+        );
+    }
+
+    #[test]
+    fn test_joint_distribution_rates_share_the_year() {
+        let repository = repository();
+        let sp500 = repository.get("sp500").unwrap();
+        let msci_world = repository.get("msci_world").unwrap();
+
+        let rates = joint_distribution_rates(
+            &[sp500, msci_world],
+            50,
+            super::Sampling::Independent,
+            &mut rand::rngs::StdRng::seed_from_u64(3),
+        )
+        .unwrap();
+        assert_eq!(rates.len(), 2);
+        for (sp500_rate, msci_world_rate) in rates[0].iter().zip(rates[1].iter()) {
+            let index = sp500
+                .values
+                .iter()
+                .position(|value| value == sp500_rate)
+                .unwrap();
+            let year = sp500.years[index];
+            assert_eq!(msci_world.value_for_year(year), Some(*msci_world_rate));
+        }
+    }
+
+    #[test]
+    fn test_inflation_is_sampled_with_the_returns() {
+        let repository = repository();
+        let sp500 = repository.get("sp500").unwrap();
+        let us_cpi = repository.get_series(Series::Inflation, "us_cpi").unwrap();
+        let returns = Interest::Distribution("sp500".to_string());
+        let inflation = Interest::Distribution("us_cpi".to_string());
+
+        let rates = sampled_rates(
+            &[(&returns, Series::Returns), (&inflation, Series::Inflation)],
+            50,
+            &repository,
+            &mut rand::rngs::StdRng::seed_from_u64(3),
+        )
+        .unwrap();
+        for (return_rate, inflation_rate) in rates[0].iter().zip(rates[1].iter()) {
+            let index = sp500
+                .values
+                .iter()
+                .position(|value| value == return_rate)
+                .unwrap();
+            assert_eq!(
+                us_cpi.value_for_year(sp500.years[index]),
+                Some(*inflation_rate)
+            );
+        }
+        assert!(inflation
+            .to_interest_rates(3, &repository, &mut rand::thread_rng())
+            .is_err());
     }
 
     #[test]
-    #[should_panic(expected = "The selected distribution doesn't exist")]
     fn test_distribution_does_not_exist() {
         let interest = Interest::Distribution("non-existing-dist".to_string());
-        interest.to_interest_rates(3);
+        assert!(matches!(
+            interest.to_interest_rates(3, &repository(), &mut rand::thread_rng()),
+            Err(crate::error::SimulationError::UnknownDistribution(name)) if name == "non-existing-dist"
+        ));
+    }
+
+    #[test]
+    fn test_taxes() {
+        let taxes = super::Taxes {
+            capital_gains_rate: 0.2,
+            dividend_yield: 0.02,
+            dividend_tax_rate: 0.3,
+            income_tax_rate: 0.4,
+            ..Default::default()
+        };
+        assert_f64_near!(taxes.dividend_tax_drag(), 0.006);
+        // Half of the balance are gains, so half of the withdrawal is taxed
+        assert_f64_near!(taxes.withdrawal_tax(1000.0, 10000.0, 5000.0), 100.0);
+        assert_f64_near!(taxes.withdrawal_tax(1000.0, 10000.0, 12000.0), 0.0);
+        assert_f64_near!(taxes.after_tax_balance(10000.0, 5000.0), 9000.0);
+
+        let deferred = super::Taxes {
+            account: super::AccountType::TaxDeferred,
+            ..taxes
+        };
+        assert_f64_near!(deferred.dividend_tax_drag(), 0.0);
+        assert_f64_near!(deferred.after_tax_balance(10000.0, 5000.0), 6000.0);
+
+        let free = super::Taxes {
+            account: super::AccountType::TaxFree,
+            ..taxes
+        };
+        assert_f64_near!(free.after_tax_balance(10000.0, 5000.0), 10000.0);
+    }
+
+    #[test]
+    fn test_contribution_schedules() {
+        let contributions = |value: serde_json::Value| -> Vec<f64> {
+            serde_json::from_value::<super::AnnualContribution>(value)
+                .unwrap()
+                .to_annual_contributions(4)
+                .iter()
+                .map(|contribution| contribution.0)
+                .collect()
+        };
+
+        assert_eq!(
+            contributions(serde_json::json!({"amount": 1000.0, "growth": 0.1, "years": 3})),
+            vec![1000.0, 1100.0, 1210.0000000000002, 0.0]
+        );
+        assert_eq!(
+            contributions(serde_json::json!({
+                "amount": 1000.0,
+                "growth": 0.5,
+                "steps": [{"year": 2, "amount": 3000.0}],
+            })),
+            vec![1000.0, 1500.0, 3000.0, 4500.0]
+        );
+
+        let inflation: super::AnnualContribution =
+            serde_json::from_value(serde_json::json!({"amount": 1000.0, "growth": "inflation"}))
+                .unwrap();
+        assert!(inflation.follows_inflation());
+        assert_eq!(
+            inflation
+                .to_annual_contributions(2)
+                .iter()
+                .map(|contribution| contribution.0)
+                .collect::<Vec<f64>>(),
+            vec![1000.0, 1000.0]
+        );
+    }
+
+    #[test]
+    fn test_guyton_klinger_guardrails() {
+        let withdrawal = Withdrawal {
+            start_year: 0,
+            strategy: WithdrawalStrategy::GuytonKlinger {
+                initial_rate: 0.05,
+                guardrail: 0.2,
+                adjustment: 0.1,
+            },
+        };
+
+        assert_eq!(withdrawal.withdrawal_amount(100000.0, None, None), 5000.0);
+        // 5000 / 80000 = 6.25% is above the 6% upper guardrail, the withdrawal is cut
+        assert_eq!(
+            withdrawal.withdrawal_amount(80000.0, Some(5000.0), Some(-0.2)),
+            4500.0
+        );
+        // 5000 / 130000 = 3.8% is below the 4% lower guardrail, the withdrawal is raised
+        assert_eq!(
+            withdrawal.withdrawal_amount(130000.0, Some(5000.0), Some(0.3)),
+            5500.0
+        );
+        // No raises after a year with negative returns
+        assert_eq!(
+            withdrawal.withdrawal_amount(130000.0, Some(5000.0), Some(-0.01)),
+            5000.0
+        );
     }
 }
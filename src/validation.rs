@@ -0,0 +1,617 @@
+use crate::distributions;
+use crate::error;
+use crate::investment_config;
+use crate::return_models;
+use crate::types;
+
+/// Largest number of Monte Carlo paths of a single run
+pub const MAX_SIMULATIONS: usize = 100_000;
+/// Longest simulated plan
+pub const MAX_YEARS: usize = 200;
+
+/// Checks the whole configuration, reporting every invalid field instead of stopping at the
+/// first one
+pub fn validate_configuration(
+    config: &investment_config::Configuration,
+    distributions: &dyn distributions::DistributionRepository,
+) -> Result<(), error::SimulationError> {
+    let mut errors: Vec<error::FieldError> = Vec::new();
+    let mut interests: Vec<(String, &types::Interest, distributions::Series)> = Vec::new();
+
+    match (&config.return_rates, &config.portfolio) {
+        (return_rates, Some(portfolio)) => {
+            if return_rates.is_some() {
+                errors.push(error::FieldError::new(
+                    "return_rates",
+                    "must not be set together with portfolio",
+                ));
+            }
+            for (i, asset) in portfolio.assets.iter().enumerate() {
+                interests.push((
+                    format!("portfolio.assets[{}].return_rates", i),
+                    &asset.return_rates,
+                    distributions::Series::Returns,
+                ));
+            }
+        }
+        (Some(return_rates), None) => interests.push((
+            "return_rates".to_string(),
+            return_rates,
+            distributions::Series::Returns,
+        )),
+        (None, None) => errors.push(error::FieldError::new(
+            "return_rates",
+            "either `return_rates` or `portfolio` must be configured",
+        )),
+    }
+    if let Some(inflation) = &config.inflation {
+        interests.push((
+            "inflation".to_string(),
+            inflation,
+            distributions::Series::Inflation,
+        ));
+    }
+    // The coverage of the interests is only checked for a valid number of years
+    for (field, interest, series) in interests.iter().filter(|_| config.years <= MAX_YEARS) {
+        if let Some(reason) = interest_error(interest, *series, config.years, distributions) {
+            errors.push(error::FieldError::new(field, reason));
+        }
+    }
+    errors.extend(shared_years_errors(&interests, distributions));
+
+    if config.years == 0 || config.years > MAX_YEARS {
+        errors.push(error::FieldError::new(
+            "years",
+            format!("must be between 1 and {}", MAX_YEARS),
+        ));
+    }
+    if let Some(reason) = contributions_error(&config.annual_contributions, config.years) {
+        errors.push(error::FieldError::new("annual_contributions", reason));
+    }
+    if config
+        .simulations
+        .is_some_and(|simulations| simulations == 0 || simulations > MAX_SIMULATIONS)
+    {
+        errors.push(error::FieldError::new(
+            "simulations",
+            format!("must be between 1 and {}", MAX_SIMULATIONS),
+        ));
+    }
+    if let Some(portfolio) = &config.portfolio {
+        errors.extend(portfolio_errors(portfolio));
+    }
+    if let Some(withdrawals) = &config.withdrawals {
+        errors.extend(withdrawal_errors(withdrawals));
+    }
+    errors.extend(fees_errors(&config.fees));
+    errors.extend(taxes_errors(&config.taxes));
+    if let Some(target) = config.target_balance {
+        if !is_non_negative(target) {
+            errors.push(error::FieldError::new(
+                "target_balance",
+                "must be a non-negative number",
+            ));
+        }
+    }
+
+    if !is_rate(config.risk_free_rate) {
+        errors.push(error::FieldError::new(
+            "risk_free_rate",
+            "must be a number greater than -1",
+        ));
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(error::SimulationError::Validation(errors))
+    }
+}
+
+fn is_non_negative(value: f64) -> bool {
+    value.is_finite() && value >= 0.0
+}
+
+fn is_rate(value: f64) -> bool {
+    value.is_finite() && value > -1.0
+}
+
+fn interest_error(
+    interest: &types::Interest,
+    series: distributions::Series,
+    years: usize,
+    distributions: &dyn distributions::DistributionRepository,
+) -> Option<String> {
+    if let Some(name) = interest.distribution_name() {
+        if distributions.get_series(series, name).is_err() {
+            let is_inflation = distributions
+                .get_series(distributions::Series::Inflation, name)
+                .is_ok();
+            return Some(match series {
+                distributions::Series::Returns if is_inflation => format!(
+                    "`{}` is an inflation series, not a distribution of returns",
+                    name
+                ),
+                distributions::Series::Returns => {
+                    format!("the distribution `{}` doesn't exist", name)
+                }
+                distributions::Series::Inflation => {
+                    format!("the inflation series `{}` doesn't exist", name)
+                }
+            });
+        }
+    }
+
+    match interest {
+        types::Interest::Single(rate) if !is_rate(*rate) => {
+            Some("must be a number greater than -1".to_string())
+        }
+        types::Interest::Multiple(rates) if rates.len() < years => Some(format!(
+            "has {} values but {} years are simulated",
+            rates.len(),
+            years
+        )),
+        types::Interest::Multiple(rates) if !rates.iter().all(|rate| is_rate(*rate)) => {
+            Some("every value must be a number greater than -1".to_string())
+        }
+        types::Interest::Historical {
+            distribution,
+            start_year,
+        } => {
+            let history = distributions.get_series(series, distribution).ok()?;
+            let covered = (*start_year..*start_year + years as i32)
+                .all(|year| history.value_for_year(year).is_some());
+            if covered {
+                None
+            } else {
+                Some(format!(
+                    "the distribution `{}` doesn't cover {} years from {}",
+                    distribution, years, start_year
+                ))
+            }
+        }
+        types::Interest::Bootstrap { block_size: 0, .. } => {
+            Some("the block size must be greater than 0".to_string())
+        }
+        types::Interest::Model(model) => model_error(model),
+        _ => None,
+    }
+}
+
+fn model_error(model: &return_models::ReturnModel) -> Option<String> {
+    let valid = match *model {
+        return_models::ReturnModel::Normal { mean, volatility }
+        | return_models::ReturnModel::LogNormal { mean, volatility }
+        | return_models::ReturnModel::StudentT {
+            mean, volatility, ..
+        } => is_rate(mean) && is_non_negative(volatility),
+        return_models::ReturnModel::GeometricBrownianMotion { drift, volatility } => {
+            drift.is_finite() && is_non_negative(volatility)
+        }
+    };
+    if !valid {
+        return Some(
+            "the mean must be greater than -1 and the volatility non-negative".to_string(),
+        );
+    }
+
+    match *model {
+        // The variance of a Student-t is infinite with 2 degrees of freedom or fewer
+        return_models::ReturnModel::StudentT {
+            degrees_of_freedom, ..
+        } if degrees_of_freedom.is_nan() || degrees_of_freedom <= 2.0 => Some(
+            "the degrees of freedom must be greater than 2 to match the volatility".to_string(),
+        ),
+        _ => None,
+    }
+}
+
+fn contributions_error(contributions: &types::AnnualContribution, years: usize) -> Option<String> {
+    match contributions {
+        types::AnnualContribution::Single(contribution) if !is_non_negative(contribution.0) => {
+            Some("must be a non-negative number".to_string())
+        }
+        types::AnnualContribution::Multiple(multiple) if multiple.len() < years => Some(format!(
+            "has {} values but {} years are simulated",
+            multiple.len(),
+            years
+        )),
+        types::AnnualContribution::Multiple(multiple)
+            if !multiple
+                .iter()
+                .all(|contribution| is_non_negative(contribution.0)) =>
+        {
+            Some("every value must be a non-negative number".to_string())
+        }
+        types::AnnualContribution::Schedule(schedule) => {
+            let growth_is_valid = match schedule.growth {
+                types::ContributionGrowth::Rate(rate) => is_rate(rate),
+                types::ContributionGrowth::Inflation(_) => true,
+            };
+            if !is_non_negative(schedule.amount.0)
+                || !schedule
+                    .steps
+                    .iter()
+                    .all(|step| is_non_negative(step.amount.0))
+            {
+                Some("every amount must be a non-negative number".to_string())
+            } else if !growth_is_valid {
+                Some("the growth must be a number greater than -1 or `inflation`".to_string())
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
+
+fn portfolio_errors(portfolio: &types::Portfolio) -> Vec<error::FieldError> {
+    let mut errors = Vec::new();
+    if portfolio.assets.is_empty() {
+        errors.push(error::FieldError::new(
+            "portfolio.assets",
+            "needs at least one asset",
+        ));
+    }
+    for (i, asset) in portfolio.assets.iter().enumerate() {
+        if !is_non_negative(asset.weight) {
+            errors.push(error::FieldError::new(
+                &format!("portfolio.assets[{}].weight", i),
+                "must be a non-negative number",
+            ));
+        }
+    }
+    let total_weight: f64 = portfolio.assets.iter().map(|asset| asset.weight).sum();
+    if !portfolio.assets.is_empty() && total_weight <= 0.0 {
+        errors.push(error::FieldError::new(
+            "portfolio.assets",
+            "the weights must add up to a positive value",
+        ));
+    }
+    if let types::Rebalancing::Threshold(threshold) = portfolio.rebalancing {
+        if !is_non_negative(threshold) {
+            errors.push(error::FieldError::new(
+                "portfolio.rebalancing",
+                "the threshold must be a non-negative number",
+            ));
+        }
+    }
+    errors
+}
+
+/// The interests sampled in the same way draw the same historical years, so their distributions
+/// must share some. Unknown distributions are reported with their interest.
+fn shared_years_errors(
+    interests: &[(String, &types::Interest, distributions::Series)],
+    distributions: &dyn distributions::DistributionRepository,
+) -> Vec<error::FieldError> {
+    let mut samplings: Vec<types::Sampling> = Vec::new();
+    for sampling in interests
+        .iter()
+        .filter_map(|(_, interest, _)| interest.sampling())
+    {
+        if !samplings.contains(&sampling) {
+            samplings.push(sampling);
+        }
+    }
+
+    let mut errors = Vec::new();
+    for sampling in samplings {
+        let mut returns: Vec<&distributions::Distribution> = Vec::new();
+        let mut inflation: Vec<&distributions::Distribution> = Vec::new();
+        for (_, interest, series) in interests
+            .iter()
+            .filter(|(_, interest, _)| interest.sampling() == Some(sampling))
+        {
+            let distribution = interest
+                .distribution_name()
+                .and_then(|name| distributions.get_series(*series, name).ok());
+            match (distribution, series) {
+                (Some(distribution), distributions::Series::Returns) => returns.push(distribution),
+                (Some(distribution), distributions::Series::Inflation) => {
+                    inflation.push(distribution)
+                }
+                (None, _) => {}
+            }
+        }
+
+        if !returns.is_empty() && types::shared_years(&returns).is_empty() {
+            errors.push(error::FieldError::new(
+                "portfolio.assets",
+                "the sampled distributions don't share any year",
+            ));
+        } else if !returns.is_empty()
+            && !inflation.is_empty()
+            && types::shared_years(&[returns, inflation].concat()).is_empty()
+        {
+            errors.push(error::FieldError::new(
+                "inflation",
+                "doesn't share any year with the sampled returns",
+            ));
+        }
+    }
+    errors
+}
+
+fn withdrawal_errors(withdrawals: &types::Withdrawal) -> Vec<error::FieldError> {
+    let is_share = |value: f64| value.is_finite() && (0.0..=1.0).contains(&value);
+    let mut errors = Vec::new();
+    let mut check = |valid: bool, field: &str, reason: &str| {
+        if !valid {
+            errors.push(error::FieldError::new(
+                &format!("withdrawals.strategy.{}", field),
+                reason,
+            ));
+        }
+    };
+
+    match withdrawals.strategy {
+        types::WithdrawalStrategy::Fixed { amount } => check(
+            is_non_negative(amount.0),
+            "amount",
+            "must be a non-negative number",
+        ),
+        types::WithdrawalStrategy::Percentage { rate } => {
+            check(is_share(rate), "rate", "must be between 0 and 1")
+        }
+        types::WithdrawalStrategy::GuytonKlinger {
+            initial_rate,
+            guardrail,
+            adjustment,
+        } => {
+            check(
+                is_share(initial_rate),
+                "initial_rate",
+                "must be between 0 and 1",
+            );
+            check(is_share(guardrail), "guardrail", "must be between 0 and 1");
+            check(
+                is_share(adjustment),
+                "adjustment",
+                "must be between 0 and 1",
+            );
+        }
+    }
+    errors
+}
+
+fn fees_errors(fees: &types::Fees) -> Vec<error::FieldError> {
+    let mut errors = Vec::new();
+    let fields = [
+        ("fees.expense_ratio", fees.expense_ratio),
+        ("fees.brokerage_fee.flat", fees.brokerage_fee.flat),
+        ("fees.brokerage_fee.rate", fees.brokerage_fee.rate),
+        ("fees.custody_fee", fees.custody_fee),
+    ];
+    for (field, value) in fields {
+        if !is_non_negative(value) {
+            errors.push(error::FieldError::new(
+                field,
+                "must be a non-negative number",
+            ));
+        }
+    }
+    if fees.annual_fee_rate() >= 1.0 {
+        errors.push(error::FieldError::new(
+            "fees",
+            "the annual fees must be lower than the whole balance",
+        ));
+    }
+    errors
+}
+
+fn taxes_errors(taxes: &types::Taxes) -> Vec<error::FieldError> {
+    let mut errors = Vec::new();
+    let rates = [
+        ("taxes.capital_gains_rate", taxes.capital_gains_rate),
+        ("taxes.dividend_tax_rate", taxes.dividend_tax_rate),
+        ("taxes.income_tax_rate", taxes.income_tax_rate),
+    ];
+    for (field, value) in rates {
+        if !(value.is_finite() && (0.0..=1.0).contains(&value)) {
+            errors.push(error::FieldError::new(field, "must be between 0 and 1"));
+        }
+    }
+    if !is_non_negative(taxes.dividend_yield) {
+        errors.push(error::FieldError::new(
+            "taxes.dividend_yield",
+            "must be a non-negative number",
+        ));
+    }
+    errors
+}
+
+#[cfg(test)]
+mod test {
+    use super::validate_configuration;
+    use crate::distributions::{
+        CsvRepository, Distribution, InMemoryRepository, DEFAULT_DIRECTORY,
+    };
+    use crate::error::SimulationError;
+    use crate::investment_config::Configuration;
+    use std::collections::HashMap;
+
+    fn invalid_fields(config: serde_json::Value) -> Vec<String> {
+        let config: Configuration = serde_json::from_value(config).unwrap();
+        let repository = CsvRepository::load(DEFAULT_DIRECTORY).unwrap();
+        match validate_configuration(&config, &repository) {
+            Err(SimulationError::Validation(errors)) => {
+                errors.into_iter().map(|error| error.field).collect()
+            }
+            Err(error) => panic!("Unexpected error {:?}", error),
+            Ok(()) => Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_valid_configuration() {
+        let fields = invalid_fields(serde_json::json!({
+            "deposit": 10000,
+            "years": 3,
+            "return_rates": [0.05, 0.02, 0.01],
+            "annual_contributions": 100.0,
+        }));
+        assert!(fields.is_empty());
+    }
+
+    #[test]
+    fn test_short_lists_are_reported() {
+        let fields = invalid_fields(serde_json::json!({
+            "deposit": 10000,
+            "years": 3,
+            "return_rates": [0.05],
+            "annual_contributions": [100.0, 100.0],
+        }));
+        assert_eq!(fields, vec!["return_rates", "annual_contributions"]);
+    }
+
+    #[test]
+    fn test_every_invalid_field_is_reported() {
+        let fields = invalid_fields(serde_json::json!({
+            "deposit": 10000,
+            "years": 0,
+            "return_rates": "unknown",
+            "annual_contributions": -100.0,
+            "simulations": 0,
+            "fees": {"expense_ratio": -0.01},
+            "taxes": {"account": "taxable", "capital_gains_rate": 1.5},
+            "withdrawals": {"strategy": {"type": "percentage", "rate": 4.0}},
+        }));
+        assert_eq!(
+            fields,
+            vec![
+                "return_rates",
+                "years",
+                "annual_contributions",
+                "simulations",
+                "withdrawals.strategy.rate",
+                "fees.expense_ratio",
+                "taxes.capital_gains_rate",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_contribution_schedules_are_validated() {
+        let fields = invalid_fields(serde_json::json!({
+            "deposit": 10000,
+            "years": 3,
+            "return_rates": 0.05,
+            "annual_contributions": {"amount": 6000.0, "growth": -1.5},
+        }));
+        assert_eq!(fields, vec!["annual_contributions"]);
+
+        let fields = invalid_fields(serde_json::json!({
+            "deposit": 10000,
+            "years": 3,
+            "return_rates": 0.05,
+            "annual_contributions": {"amount": 6000.0, "growth": "inflation", "years": 2},
+        }));
+        assert!(fields.is_empty());
+    }
+
+    #[test]
+    fn test_student_t_needs_a_finite_variance() {
+        let fields = invalid_fields(serde_json::json!({
+            "deposit": 10000,
+            "years": 3,
+            "return_rates": {"model": "student_t", "mean": 0.07, "volatility": 0.15, "degrees_of_freedom": 2.0},
+            "annual_contributions": 0.0,
+        }));
+        assert_eq!(fields, vec!["return_rates"]);
+    }
+
+    #[test]
+    fn test_huge_runs_are_rejected() {
+        let fields = invalid_fields(serde_json::json!({
+            "deposit": 10000,
+            "years": 100000000000000u64,
+            "return_rates": {"distribution": "sp500", "start_year": 2000},
+            "annual_contributions": 0.0,
+            "simulations": 100000000000000u64,
+        }));
+        assert_eq!(fields, vec!["years", "simulations"]);
+    }
+
+    #[test]
+    fn test_historical_coverage_is_reported() {
+        let fields = invalid_fields(serde_json::json!({
+            "deposit": 10000,
+            "years": 10,
+            "return_rates": {"distribution": "sp500", "start_year": 2020},
+            "annual_contributions": 0.0,
+        }));
+        assert_eq!(fields, vec!["return_rates"]);
+    }
+
+    #[test]
+    fn test_inflation_series_are_not_returns() {
+        let fields = invalid_fields(serde_json::json!({
+            "deposit": 10000,
+            "years": 3,
+            "return_rates": "us_cpi",
+            "annual_contributions": 0.0,
+            "inflation": "sp500",
+        }));
+        assert_eq!(fields, vec!["return_rates", "inflation"]);
+
+        let fields = invalid_fields(serde_json::json!({
+            "deposit": 10000,
+            "years": 3,
+            "return_rates": "sp500",
+            "annual_contributions": 0.0,
+            "inflation": "us_cpi",
+        }));
+        assert!(fields.is_empty());
+    }
+
+    #[test]
+    fn test_return_rates_are_exclusive_with_a_portfolio() {
+        let fields = invalid_fields(serde_json::json!({
+            "deposit": 10000,
+            "years": 3,
+            "return_rates": 0.05,
+            "annual_contributions": 0.0,
+            "portfolio": {
+                "assets": [{"name": "sp500", "weight": 1.0, "return_rates": "sp500"}],
+            },
+        }));
+        assert_eq!(fields, vec!["return_rates"]);
+    }
+
+    #[test]
+    fn test_portfolio_distributions_must_share_years() {
+        let config: Configuration = serde_json::from_value(serde_json::json!({
+            "deposit": 10000,
+            "years": 2,
+            "annual_contributions": 0.0,
+            "portfolio": {
+                "assets": [
+                    {"name": "old", "weight": 0.5, "return_rates": "old"},
+                    {"name": "new", "weight": 0.5, "return_rates": "new"},
+                ],
+                "rebalancing": "annually",
+            },
+        }))
+        .unwrap();
+        let distribution = |years: Vec<i32>| Distribution {
+            values: vec![0.05; years.len()],
+            years,
+        };
+        let repository = InMemoryRepository::new(HashMap::from([
+            ("old".to_string(), distribution(vec![1990, 1991])),
+            ("new".to_string(), distribution(vec![2020, 2021])),
+        ]));
+
+        match validate_configuration(&config, &repository) {
+            Err(SimulationError::Validation(errors)) => {
+                assert_eq!(errors[0].field, "portfolio.assets")
+            }
+            _ => panic!("The disjoint distributions must be reported"),
+        }
+        assert!(matches!(
+            config.simulate(0, &repository),
+            Err(SimulationError::NoSharedYears)
+        ));
+    }
+}